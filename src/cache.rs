@@ -0,0 +1,240 @@
+// Object pools are per-backend caches of the catalog tables. They are kept in sync by relcache
+// invalidation: every change of a catalog table sends an invalidation message for the table,
+// which is delivered to all backends when the transaction commits, and the pools caching the
+// table are cleared by the relcache callback.
+
+use std::{
+    ffi::CStr,
+    sync::{LazyLock, Mutex},
+};
+
+use dashmap::DashMap;
+use pgrx::pg_sys;
+
+use crate::{
+    model::invalidate_models,
+    text_analyzer::invalidate_text_analyzers,
+    token_filter::{
+        invalidate_hunspell_dictionaries, invalidate_keyword_markers, invalidate_stemmer_overrides,
+        invalidate_stopwords, invalidate_synonyms, invalidate_word_lists,
+    },
+    tokenizer::invalidate_tokenizers,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Catalog {
+    TextAnalyzer,
+    Tokenizer,
+    Model,
+    Stopwords,
+    Synonym,
+    KeywordMarker,
+    StemmerOverride,
+    HunspellDict,
+    WordList,
+    TextSearchConfig,
+}
+
+impl Catalog {
+    const ALL: [Catalog; 10] = [
+        Catalog::TextAnalyzer,
+        Catalog::Tokenizer,
+        Catalog::Model,
+        Catalog::Stopwords,
+        Catalog::Synonym,
+        Catalog::KeywordMarker,
+        Catalog::StemmerOverride,
+        Catalog::HunspellDict,
+        Catalog::WordList,
+        Catalog::TextSearchConfig,
+    ];
+
+    fn table_name(self) -> &'static CStr {
+        match self {
+            Catalog::TextAnalyzer => c"text_analyzer",
+            Catalog::Tokenizer => c"tokenizer",
+            Catalog::Model => c"model",
+            Catalog::Stopwords => c"stopwords",
+            Catalog::Synonym => c"synonym",
+            Catalog::KeywordMarker => c"keyword_marker",
+            Catalog::StemmerOverride => c"stemmer_override",
+            Catalog::HunspellDict => c"hunspell_dict",
+            Catalog::WordList => c"word_list",
+            Catalog::TextSearchConfig => c"text_search_config",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        self.table_name().to_str().unwrap()
+    }
+
+    pub fn from_name(name: &str) -> Self {
+        Catalog::ALL
+            .into_iter()
+            .find(|catalog| catalog.as_str() == name)
+            .unwrap_or_else(|| panic!("Unknown catalog: {}", name))
+    }
+
+    pub fn display_name(self) -> String {
+        self.as_str().replace('_', " ")
+    }
+
+    // only text analyzers, tokenizers, custom models and text search configs depend on other
+    // objects
+    pub fn drop_function(self) -> Option<&'static str> {
+        match self {
+            Catalog::TextAnalyzer => Some("drop_text_analyzer"),
+            Catalog::Tokenizer => Some("drop_tokenizer"),
+            Catalog::Model => Some("drop_custom_model"),
+            Catalog::TextSearchConfig => Some("drop_text_search_config"),
+            _ => None,
+        }
+    }
+
+    // clear the pool of the catalog, and the pools of objects embedding them
+    fn clear(self) {
+        match self {
+            Catalog::TextAnalyzer => invalidate_text_analyzers(),
+            Catalog::Tokenizer => invalidate_tokenizers(),
+            Catalog::Model => {
+                invalidate_models();
+                invalidate_tokenizers();
+            }
+            Catalog::Stopwords => {
+                invalidate_stopwords();
+                invalidate_text_analyzers();
+            }
+            Catalog::Synonym => {
+                invalidate_synonyms();
+                invalidate_text_analyzers();
+            }
+            Catalog::KeywordMarker => {
+                invalidate_keyword_markers();
+                invalidate_text_analyzers();
+            }
+            Catalog::StemmerOverride => {
+                invalidate_stemmer_overrides();
+                invalidate_text_analyzers();
+            }
+            Catalog::HunspellDict => {
+                invalidate_hunspell_dictionaries();
+                invalidate_text_analyzers();
+            }
+            Catalog::WordList => {
+                invalidate_word_lists();
+                invalidate_text_analyzers();
+            }
+            // nothing is cached, dictionaries look up the text analyzer by name
+            Catalog::TextSearchConfig => {}
+        }
+    }
+}
+
+// the relcache callback cannot access the catalogs, so the oids are resolved in advance
+static CATALOG_OIDS: LazyLock<DashMap<pg_sys::Oid, Catalog>> = LazyLock::new(DashMap::new);
+
+// catalogs to be cleared at the end of the current transaction
+static PENDING_CATALOGS: Mutex<Vec<Catalog>> = Mutex::new(Vec::new());
+
+fn catalog_oid(catalog: Catalog) -> pg_sys::Oid {
+    let oid = unsafe {
+        let namespace = pg_sys::get_namespace_oid(c"tokenizer_catalog".as_ptr(), false);
+        pg_sys::get_relname_relid(catalog.table_name().as_ptr(), namespace)
+    };
+    if oid == pg_sys::InvalidOid {
+        panic!("Catalog table not found: {:?}", catalog.table_name());
+    }
+
+    CATALOG_OIDS.insert(oid, catalog);
+    oid
+}
+
+fn add_pending(catalog: Catalog) {
+    let mut pending = PENDING_CATALOGS.lock().unwrap();
+    if !pending.contains(&catalog) {
+        pending.push(catalog);
+    }
+}
+
+// Should be called before loading objects from the catalog table into the pool.
+pub fn watch_catalog(catalog: Catalog) {
+    catalog_oid(catalog);
+
+    // the objects are loaded with the transaction snapshot, which may be outdated after the
+    // transaction ends
+    if unsafe { pg_sys::XactIsoLevel } >= pg_sys::XACT_REPEATABLE_READ as i32 {
+        add_pending(catalog);
+    }
+}
+
+// Should be called after the catalog table or a source table of it is modified. Other backends
+// clear their pools after the transaction commits, and the current backend clears its pools
+// after the current command or when the transaction aborts.
+pub fn invalidate_catalog(catalog: Catalog) {
+    let oid = catalog_oid(catalog);
+    unsafe { pg_sys::CacheInvalidateRelcacheByRelid(oid) };
+    add_pending(catalog);
+}
+
+#[pgrx::pg_guard]
+unsafe extern "C-unwind" fn relcache_callback(_arg: pg_sys::Datum, relid: pg_sys::Oid) {
+    // an invalid oid means all relations should be invalidated
+    if relid == pg_sys::InvalidOid {
+        for catalog in Catalog::ALL {
+            catalog.clear();
+        }
+        return;
+    }
+
+    let catalog = CATALOG_OIDS.get(&relid).map(|entry| *entry.value());
+    if let Some(catalog) = catalog {
+        catalog.clear();
+    }
+}
+
+fn clear_pending() {
+    let pending = std::mem::take(&mut *PENDING_CATALOGS.lock().unwrap());
+    for catalog in pending {
+        catalog.clear();
+    }
+}
+
+#[pgrx::pg_guard]
+unsafe extern "C-unwind" fn xact_callback(
+    event: pg_sys::XactEvent::Type,
+    _arg: *mut std::ffi::c_void,
+) {
+    match event {
+        pg_sys::XactEvent::XACT_EVENT_COMMIT
+        | pg_sys::XactEvent::XACT_EVENT_PARALLEL_COMMIT
+        | pg_sys::XactEvent::XACT_EVENT_ABORT
+        | pg_sys::XactEvent::XACT_EVENT_PARALLEL_ABORT
+        | pg_sys::XactEvent::XACT_EVENT_PREPARE => clear_pending(),
+        _ => {}
+    }
+}
+
+#[pgrx::pg_guard]
+unsafe extern "C-unwind" fn subxact_callback(
+    event: pg_sys::SubXactEvent::Type,
+    _my_subid: pg_sys::SubTransactionId,
+    _parent_subid: pg_sys::SubTransactionId,
+    _arg: *mut std::ffi::c_void,
+) {
+    // objects created in the aborted subtransaction may be cached, the pending list is kept
+    // since the changes of the parent transaction are not settled yet
+    if event == pg_sys::SubXactEvent::SUBXACT_EVENT_ABORT_SUB {
+        let pending = PENDING_CATALOGS.lock().unwrap().clone();
+        for catalog in pending {
+            catalog.clear();
+        }
+    }
+}
+
+pub fn init() {
+    unsafe {
+        pg_sys::CacheRegisterRelcacheCallback(Some(relcache_callback), pg_sys::Datum::from(0));
+        pg_sys::RegisterXactCallback(Some(xact_callback), std::ptr::null_mut());
+        pg_sys::RegisterSubXactCallback(Some(subxact_callback), std::ptr::null_mut());
+    }
+}
@@ -5,13 +5,67 @@ use std::{borrow::Cow, sync::Arc};
 
 use serde::{Deserialize, Serialize};
 use to_lowercase::ToLowercase;
-use unicode_normalization::UnicodeNormalizationConfig;
+pub use unicode_normalization::UnicodeNormalizationConfig;
+use unicode_segmentation::UnicodeSegmentation;
 
 pub trait CharacterFilter {
     fn apply(&self, text: &mut Cow<str>);
+
+    // Like `apply`, and returns the offset map from the filtered text to the original one. By
+    // default the filter is applied to each grapheme cluster separately.
+    fn apply_with_offsets(&self, text: &str) -> (String, OffsetMap) {
+        let mut filtered = String::with_capacity(text.len());
+        let mut map = OffsetMap::default();
+        for (start, grapheme) in text.grapheme_indices(true) {
+            let mut segment = Cow::Borrowed(grapheme);
+            self.apply(&mut segment);
+            map.push(filtered.len(), start);
+            filtered.push_str(&segment);
+        }
+        map.push(filtered.len(), text.len());
+
+        (filtered, map)
+    }
 }
 pub type CharacterFilterPtr = Arc<dyn CharacterFilter + Send + Sync>;
 
+// Maps byte offsets of a filtered text to the text before filtering. Each segment is filtered as
+// a whole, so offsets inside a segment are moved to its boundaries.
+#[derive(Debug, Default)]
+pub struct OffsetMap {
+    // the start of each segment in the filtered text and in the original text, ending with the
+    // lengths of both texts
+    segments: Vec<(usize, usize)>,
+}
+
+impl OffsetMap {
+    pub fn push(&mut self, filtered: usize, original: usize) {
+        self.segments.push((filtered, original));
+    }
+
+    // the start of the segment containing the offset
+    pub fn start(&self, offset: usize) -> usize {
+        let i = self
+            .segments
+            .partition_point(|(filtered, _)| *filtered <= offset);
+        self.segments[i.saturating_sub(1)].1
+    }
+
+    // the end of the segment containing the offset
+    pub fn end(&self, offset: usize) -> usize {
+        let i = self
+            .segments
+            .partition_point(|(filtered, _)| *filtered < offset);
+        match self.segments.get(i) {
+            Some((_, original)) => *original,
+            None => self
+                .segments
+                .last()
+                .map_or(offset, |(_, original)| *original),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(deny_unknown_fields)]
@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 
-use super::CharacterFilter;
+use super::{CharacterFilter, OffsetMap};
 
 pub struct ToLowercase;
 
@@ -8,4 +8,18 @@ impl CharacterFilter for ToLowercase {
     fn apply(&self, text: &mut Cow<str>) {
         *text = Cow::Owned(text.to_lowercase());
     }
+
+    // the final sigma rule of `str::to_lowercase` does not change the length of a character, so
+    // each character is a segment
+    fn apply_with_offsets(&self, text: &str) -> (String, OffsetMap) {
+        let mut map = OffsetMap::default();
+        let mut filtered_len = 0;
+        for (start, c) in text.char_indices() {
+            map.push(filtered_len, start);
+            filtered_len += c.to_lowercase().map(char::len_utf8).sum::<usize>();
+        }
+        map.push(filtered_len, text.len());
+
+        (text.to_lowercase(), map)
+    }
 }
@@ -19,6 +19,17 @@ pub enum UnicodeNormalizationConfig {
     NFKD,
 }
 
+impl UnicodeNormalizationConfig {
+    pub fn normalize(&self, text: &str) -> String {
+        match self {
+            UnicodeNormalizationConfig::NFC => text.nfc().collect(),
+            UnicodeNormalizationConfig::NFD => text.nfd().collect(),
+            UnicodeNormalizationConfig::NFKC => text.nfkc().collect(),
+            UnicodeNormalizationConfig::NFKD => text.nfkd().collect(),
+        }
+    }
+}
+
 pub struct NFC;
 pub struct NFD;
 pub struct NFKC;
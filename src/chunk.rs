@@ -0,0 +1,154 @@
+use std::{ops::Range, sync::LazyLock};
+
+use pgrx::{iter::TableIterator, name};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::tokenizer::get_tokenizer;
+
+// a blank line, which may contain spaces
+static PARAGRAPH_SEPARATOR: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"\n[^\S\n]*\n\s*").unwrap());
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Boundary {
+    Sentence,
+    Paragraph,
+    Token,
+}
+
+impl Boundary {
+    fn parse(boundary: &str) -> Self {
+        match boundary {
+            "sentence" => Boundary::Sentence,
+            "paragraph" => Boundary::Paragraph,
+            "token" => Boundary::Token,
+            _ => panic!(
+                "Unknown boundary: {}, expected one of sentence, paragraph, token",
+                boundary
+            ),
+        }
+    }
+
+    // byte offsets of the segments, starting with 0
+    fn segment_starts(self, text: &str) -> Vec<usize> {
+        match self {
+            Boundary::Sentence => text
+                .split_sentence_bound_indices()
+                .map(|(i, _)| i)
+                .collect(),
+            Boundary::Paragraph => std::iter::once(0)
+                .chain(PARAGRAPH_SEPARATOR.find_iter(text).map(|m| m.end()))
+                .collect(),
+            Boundary::Token => vec![0],
+        }
+    }
+}
+
+// Pack tokens into windows of at most `max_tokens`. A window ends before the first token of a
+// segment if possible, and the next window starts up to `overlap` tokens earlier, at the first
+// token of a segment if possible. Segments longer than `max_tokens` are split at any token.
+fn pack(breaks: &[usize], len: usize, max_tokens: usize, overlap: usize) -> Vec<Range<usize>> {
+    let mut windows = Vec::new();
+    let mut start = 0;
+    while start < len {
+        let limit = (start + max_tokens).min(len);
+        let end = match breaks[..breaks.partition_point(|b| *b <= limit)].last() {
+            _ if limit == len => len,
+            Some(b) if *b > start => *b,
+            _ => limit,
+        };
+        windows.push(start..end);
+        if end == len {
+            break;
+        }
+
+        let min_start = end.saturating_sub(overlap).max(start + 1);
+        start = match breaks[breaks.partition_point(|b| *b < min_start)..].first() {
+            Some(b) if *b <= end => *b,
+            _ => min_start,
+        };
+    }
+    windows
+}
+
+// Split a text into chunks of at most `max_tokens` tokens. The chunks of `sentence` and
+// `paragraph` boundaries cover the whole segments, including the text without tokens like
+// spaces and stopwords, while the chunks of `token` boundary are cut at the tokens.
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn chunk_text(
+    text: &str,
+    tokenizer_name: &str,
+    max_tokens: i32,
+    overlap_tokens: default!(i32, 0),
+    boundary: default!(&str, "'sentence'"),
+) -> TableIterator<
+    'static,
+    (
+        name!(chunk_index, i32),
+        name!(chunk_text, String),
+        name!(start_offset, i32),
+        name!(end_offset, i32),
+        name!(token_count, i32),
+    ),
+> {
+    if max_tokens <= 0 {
+        panic!("max_tokens must be positive: {}", max_tokens);
+    }
+    if overlap_tokens < 0 || overlap_tokens >= max_tokens {
+        panic!(
+            "overlap_tokens must be between 0 and max_tokens - 1: {}",
+            overlap_tokens
+        );
+    }
+    let boundary = Boundary::parse(boundary);
+
+    let tokenizer = get_tokenizer(tokenizer_name);
+    let tokens = tokenizer.tokenize_with_offsets(text);
+    let segment_starts = boundary.segment_starts(text);
+    let segment_of = |offset: usize| segment_starts.partition_point(|s| *s <= offset) - 1;
+    let segments = tokens
+        .iter()
+        .map(|(_, range)| segment_of(range.start))
+        .collect::<Vec<_>>();
+    // indices of the first tokens of the segments
+    let breaks = (0..tokens.len())
+        .filter(|i| *i == 0 || segments[*i] != segments[*i - 1])
+        .collect::<Vec<_>>();
+    let is_break = |i: usize| boundary != Boundary::Token && breaks.binary_search(&i).is_ok();
+
+    let start_offset = |i: usize| match i {
+        0 => 0,
+        _ if is_break(i) => segment_starts[segments[i]],
+        _ => tokens[i].1.start,
+    };
+    let end_offset = |j: usize| match j {
+        _ if j == tokens.len() => text.len(),
+        _ if is_break(j) => segment_starts[segments[j]],
+        _ => tokens[j - 1].1.end,
+    };
+
+    let boundaries = text.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+    let char_offset = |offset: usize| boundaries.partition_point(|b| *b < offset) as i32;
+
+    let rows = pack(
+        &breaks,
+        tokens.len(),
+        max_tokens as usize,
+        overlap_tokens as usize,
+    )
+    .into_iter()
+    .enumerate()
+    .map(|(index, window)| {
+        let start = start_offset(window.start);
+        let end = end_offset(window.end).max(start);
+        (
+            index as i32,
+            text[start..end].to_string(),
+            char_offset(start),
+            char_offset(end),
+            window.len() as i32,
+        )
+    })
+    .collect::<Vec<_>>();
+    TableIterator::new(rows)
+}
@@ -0,0 +1,116 @@
+use pgrx::pg_sys::panic::ErrorReportable;
+
+use crate::cache::Catalog;
+
+// `kind` and `name` depend on `ref_kind` and `ref_name`, kinds are the names of catalog tables
+pgrx::extension_sql!(
+    r#"
+CREATE TABLE tokenizer_catalog.dependency (
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    ref_kind TEXT NOT NULL,
+    ref_name TEXT NOT NULL,
+    PRIMARY KEY (kind, name, ref_kind, ref_name)
+);
+"#,
+    name = "dependency_table"
+);
+
+// replace the recorded dependencies of the object
+pub fn record_dependencies(catalog: Catalog, name: &str, dependencies: &[(Catalog, String)]) {
+    remove_dependencies(catalog, name);
+
+    pgrx::Spi::connect_mut(|client| {
+        for (ref_catalog, ref_name) in dependencies {
+            client
+                .update(
+                    r#"
+                    INSERT INTO tokenizer_catalog.dependency (kind, name, ref_kind, ref_name)
+                    VALUES ($1, $2, $3, $4)
+                    ON CONFLICT DO NOTHING
+                    "#,
+                    None,
+                    &[
+                        catalog.as_str().into(),
+                        name.into(),
+                        ref_catalog.as_str().into(),
+                        ref_name.as_str().into(),
+                    ],
+                )
+                .unwrap();
+        }
+    });
+}
+
+pub fn remove_dependencies(catalog: Catalog, name: &str) {
+    pgrx::Spi::connect_mut(|client| {
+        client
+            .update(
+                "DELETE FROM tokenizer_catalog.dependency WHERE kind = $1 AND name = $2",
+                None,
+                &[catalog.as_str().into(), name.into()],
+            )
+            .unwrap();
+    });
+}
+
+// objects that depend on the given object directly
+pub fn dependents(catalog: Catalog, name: &str) -> Vec<(Catalog, String)> {
+    pgrx::Spi::connect(|client| {
+        let tuptable = client
+            .select(
+                r#"
+                SELECT kind, name FROM tokenizer_catalog.dependency
+                WHERE ref_kind = $1 AND ref_name = $2
+                ORDER BY kind, name
+                "#,
+                None,
+                &[catalog.as_str().into(), name.into()],
+            )
+            .unwrap_or_report();
+
+        tuptable
+            .map(|tup| {
+                let kind: String = tup.get(1).unwrap_or_report().expect("no kind value");
+                let name: String = tup.get(2).unwrap_or_report().expect("no name value");
+                (Catalog::from_name(&kind), name)
+            })
+            .collect()
+    })
+}
+
+// Should be called before dropping an object. It fails if other objects depend on it, unless
+// `cascade` is true, in which case the dependents are dropped first.
+pub fn drop_dependents(catalog: Catalog, name: &str, cascade: bool) {
+    let dependents = dependents(catalog, name);
+    if dependents.is_empty() {
+        return;
+    }
+
+    if !cascade {
+        let list = dependents
+            .iter()
+            .map(|(kind, name)| format!("{} {}", kind.display_name(), name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        panic!(
+            "Cannot drop {} {} because other objects depend on it: {}. Use cascade => true to drop them too",
+            catalog.display_name(),
+            name,
+            list
+        );
+    }
+
+    for (kind, name) in dependents {
+        let Some(function) = kind.drop_function() else {
+            panic!("Cannot drop {} {}", kind.display_name(), name);
+        };
+        pgrx::notice!("Drop cascades to {} {}", kind.display_name(), name);
+        let query = format!("SELECT tokenizer_catalog.{}($1, cascade => true)", function);
+        pgrx::Spi::connect_mut(|client| {
+            client
+                .update(&query, None, &[name.as_str().into()])
+                .unwrap();
+        });
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::HashSet, ops::Range};
+
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::{
+    text_analyzer::{find_text_analyzer, TextAnalyzerPtr},
+    tokenizer::get_tokenizer,
+};
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+struct HighlightOptions {
+    start_sel: String,
+    stop_sel: String,
+    // 0 means highlighting the whole text
+    max_fragments: usize,
+    // the number of characters of a fragment, it's extended to word boundaries
+    #[validate(range(min = 1))]
+    fragment_size: usize,
+    fragment_delimiter: String,
+}
+
+impl Default for HighlightOptions {
+    fn default() -> Self {
+        Self {
+            start_sel: "<b>".to_string(),
+            stop_sel: "</b>".to_string(),
+            max_fragments: 0,
+            fragment_size: 100,
+            fragment_delimiter: " ... ".to_string(),
+        }
+    }
+}
+
+// the name can be either a text analyzer or a tokenizer
+fn resolve_text_analyzer(name: &str) -> TextAnalyzerPtr {
+    match find_text_analyzer(name) {
+        Some(text_analyzer) => text_analyzer,
+        None => get_tokenizer(name).text_analyzer.clone(),
+    }
+}
+
+// byte ranges of the tokens in the text that match any token of the query, sorted and merged
+fn find_matches(text_analyzer: &TextAnalyzerPtr, text: &str, query: &str) -> Vec<Range<usize>> {
+    let query_tokens = text_analyzer
+        .apply(query)
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    let mut matches = text_analyzer
+        .apply_with_spans(text)
+        .into_iter()
+        .filter(|(token, _)| query_tokens.contains(token))
+        .map(|(_, span)| span.start..span.end)
+        .filter(|range| !range.is_empty())
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|range| (range.start, range.end));
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(matches.len());
+    for range in matches {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+// extend the range to the boundaries of the words at both ends
+fn extend_to_words(text: &str, range: Range<usize>) -> Range<usize> {
+    let start = match text[..range.start].rfind(char::is_whitespace) {
+        Some(i) => i + text[i..].chars().next().unwrap().len_utf8(),
+        None => 0,
+    };
+    let end = match text[range.end..].find(char::is_whitespace) {
+        Some(i) => range.end + i,
+        None => text.len(),
+    };
+    start..end
+}
+
+// Choose the fragments with the most matches, a fragment is a window of `fragment_size`
+// characters around a match. The fragments are returned in the order of the text.
+fn select_fragments(
+    text: &str,
+    matches: &[Range<usize>],
+    options: &HighlightOptions,
+) -> Vec<Range<usize>> {
+    let mut boundaries = text.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+    boundaries.push(text.len());
+    let char_count = boundaries.len() - 1;
+    let to_char = |offset: usize| boundaries.partition_point(|b| *b < offset);
+    let window = |start: usize| {
+        let end = (start + options.fragment_size).min(char_count);
+        let start = end.saturating_sub(options.fragment_size);
+        boundaries[start]..boundaries[end]
+    };
+
+    // the beginning of the text is shown if nothing matches
+    if matches.is_empty() {
+        return vec![extend_to_words(text, window(0))];
+    }
+
+    let mut candidates = matches
+        .iter()
+        .map(|range| {
+            let len = to_char(range.end) - to_char(range.start);
+            let context = options.fragment_size.saturating_sub(len) / 2;
+            let fragment = window(to_char(range.start).saturating_sub(context));
+            let fragment = extend_to_words(text, fragment.start..fragment.end.max(range.end));
+            let score = matches
+                .iter()
+                .filter(|m| fragment.start <= m.start && m.end <= fragment.end)
+                .count();
+            (score, fragment)
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(score, fragment)| (std::cmp::Reverse(*score), fragment.start));
+
+    let mut fragments: Vec<Range<usize>> = Vec::new();
+    for (_, fragment) in candidates {
+        if fragments.len() >= options.max_fragments {
+            break;
+        }
+        let overlapped = fragments
+            .iter()
+            .any(|f| fragment.start < f.end && f.start < fragment.end);
+        if !overlapped {
+            fragments.push(fragment);
+        }
+    }
+    fragments.sort_by_key(|fragment| fragment.start);
+    fragments
+}
+
+fn mark(
+    text: &str,
+    fragment: Range<usize>,
+    matches: &[Range<usize>],
+    options: &HighlightOptions,
+) -> String {
+    let mut result = String::with_capacity(fragment.len());
+    let mut last = fragment.start;
+    for range in matches
+        .iter()
+        .filter(|m| fragment.start <= m.start && m.end <= fragment.end)
+    {
+        result.push_str(&text[last..range.start]);
+        result.push_str(&options.start_sel);
+        result.push_str(&text[range.clone()]);
+        result.push_str(&options.stop_sel);
+        last = range.end;
+    }
+    result.push_str(&text[last..fragment.end]);
+    result
+}
+
+// Like `ts_headline`, the text and the query are analyzed with the same text analyzer, and the
+// tokens of the text that match the query are marked.
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn highlight(
+    text: &str,
+    query: &str,
+    name: &str,
+    options: default!(Option<&str>, "NULL"),
+) -> String {
+    let options: HighlightOptions = toml::from_str(options.unwrap_or_default()).unwrap();
+    options.validate().unwrap();
+
+    let text_analyzer = resolve_text_analyzer(name);
+    let matches = find_matches(&text_analyzer, text, query);
+
+    if options.max_fragments == 0 {
+        return mark(text, 0..text.len(), &matches, &options);
+    }
+    select_fragments(text, &matches, &options)
+        .into_iter()
+        .map(|fragment| mark(text, fragment, &matches, &options).trim().to_string())
+        .collect::<Vec<_>>()
+        .join(&options.fragment_delimiter)
+}
@@ -1,10 +1,16 @@
+pub mod cache;
 pub mod character_filter;
+pub mod chunk;
+pub mod dependency;
+pub mod highlight;
 pub mod model;
 pub mod pre_tokenizer;
 pub mod text_analyzer;
+pub mod text_search;
 pub mod token_filter;
 pub mod tokenizer;
 pub mod utils;
+pub mod views;
 
 ::pgrx::pg_module_magic!();
 
@@ -29,6 +35,7 @@ extern "C-unwind" fn _PG_init() {
 
     pre_tokenizer::init();
     model::init();
+    cache::init();
 }
 
 #[cfg(test)]
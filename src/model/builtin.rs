@@ -1,6 +1,6 @@
 use std::sync::{Arc, LazyLock};
 
-use super::TokenizerModelPtr;
+use super::{TockenModel, TokenizerModelPtr};
 
 macro_rules! MODEL_DIR {
     () => {
@@ -13,8 +13,8 @@ static BERT_BASE_UNCASED_BYTES: &[u8] =
 static BERT_BASE_UNCASED: LazyLock<Arc<tokenizers::Tokenizer>> =
     LazyLock::new(|| Arc::new(tokenizers::Tokenizer::from_bytes(BERT_BASE_UNCASED_BYTES).unwrap()));
 static WIKI_TOCKEN_STR: &str = include_str!(concat!(MODEL_DIR!(), "/wiki_tocken.json"));
-static WIKI_TOCKEN: LazyLock<Arc<tocken::tokenizer::Tokenizer>> =
-    LazyLock::new(|| Arc::new(tocken::tokenizer::Tokenizer::loads(WIKI_TOCKEN_STR)));
+static WIKI_TOCKEN: LazyLock<Arc<TockenModel>> =
+    LazyLock::new(|| Arc::new(TockenModel::loads(WIKI_TOCKEN_STR)));
 static GEMMA2B_BYTES: &[u8] = include_bytes!(concat!(MODEL_DIR!(), "/gemma2b.json"));
 static GEMMA2B: LazyLock<Arc<tokenizers::Tokenizer>> =
     LazyLock::new(|| Arc::new(tokenizers::Tokenizer::from_bytes(GEMMA2B_BYTES).unwrap()));
@@ -24,11 +24,10 @@ static LLMLINGUA2: LazyLock<Arc<tokenizers::Tokenizer>> =
 
 pub const PRELOAD_MODELS: &[&str] = &["llmlingua2"];
 
+pub const BUILTIN_MODELS: &[&str] = &["bert_base_uncased", "wiki_tocken", "gemma2b", "llmlingua2"];
+
 pub fn is_builtin_model(name: &str) -> bool {
-    matches!(
-        name,
-        "bert_base_uncased" | "wiki_tocken" | "gemma2b" | "llmlingua2"
-    )
+    BUILTIN_MODELS.contains(&name)
 }
 
 pub fn get_builtin_model(name: &str) -> Option<TokenizerModelPtr> {
@@ -40,3 +39,18 @@ pub fn get_builtin_model(name: &str) -> Option<TokenizerModelPtr> {
         _ => None,
     }
 }
+
+// the vocabulary sizes of builtin models, to report them without loading the models
+const BUILTIN_VOCAB_SIZES: &[(&str, i32)] = &[
+    ("bert_base_uncased", 30522),
+    ("wiki_tocken", 90139),
+    ("gemma2b", 256000),
+    ("llmlingua2", 250002),
+];
+
+pub fn builtin_vocab_size(name: &str) -> Option<i32> {
+    BUILTIN_VOCAB_SIZES
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, size)| *size)
+}
@@ -1,14 +1,15 @@
-use std::{collections::HashMap, ffi::CStr, sync::Arc};
+use std::{collections::HashMap, ffi::CStr, ops::Range, sync::Arc};
 
-use pgrx::{
-    pg_sys::{panic::ErrorReportable, AsPgCStr},
-    prelude::PgHeapTuple,
-    WhoAllocated,
-};
+use pgrx::{pg_sys::panic::ErrorReportable, prelude::PgHeapTuple, WhoAllocated};
 use serde::{Deserialize, Serialize};
 use validator::{Validate, ValidationError};
 
-use crate::{text_analyzer::get_text_analyzer, utils::spi_get_one};
+use crate::{
+    cache::{invalidate_catalog, Catalog},
+    dependency::{dependents, drop_dependents, record_dependencies, remove_dependencies},
+    text_analyzer::get_text_analyzer,
+    utils::{quote_identifier, spi_get_one},
+};
 
 use super::{validate_new_model_name, ModelConfig, TokenizerModel, MODEL_OBJECT_POOL};
 
@@ -25,6 +26,30 @@ impl CustomModel {
     }
 }
 
+impl CustomModel {
+    // the ids of the tokens in the vocabulary, unknown tokens are absent
+    fn lookup(&self, tokens: &[String]) -> HashMap<String, u32> {
+        let query = format!(
+            r#"SELECT id, token FROM tokenizer_catalog."model_{}" WHERE token = ANY($1)"#,
+            self.name
+        );
+
+        let mut token_map = HashMap::new();
+        pgrx::Spi::connect(|client| {
+            let tuptable = client
+                .select(&query, None, &[tokens.to_vec().into()])
+                .unwrap_or_report();
+            for tup in tuptable {
+                let id: i32 = tup.get(1).unwrap_or_report().expect("no id value");
+                let id = u32::try_from(id).expect("id is not a valid u32");
+                let token: String = tup.get(2).unwrap_or_report().expect("no token value");
+                token_map.insert(token, id);
+            }
+        });
+        token_map
+    }
+}
+
 impl TokenizerModel for CustomModel {
     fn apply(&self, text: String) -> Vec<u32> {
         let query = format!(
@@ -42,28 +67,104 @@ impl TokenizerModel for CustomModel {
     }
 
     fn apply_batch(&self, tokens: Vec<String>) -> Vec<u32> {
+        let token_map = self.lookup(&tokens);
+        tokens
+            .into_iter()
+            .filter_map(|token| token_map.get(&token).copied())
+            .collect()
+    }
+
+    fn count_batch(&self, tokens: Vec<String>) -> usize {
         let query = format!(
-            r#"SELECT id, token FROM tokenizer_catalog."model_{}" WHERE token = ANY($1)"#,
+            r#"SELECT count(*) FROM unnest($1::text[]) AS t(token) JOIN tokenizer_catalog."model_{}" m USING (token)"#,
             self.name
         );
+        let count = spi_get_one::<i64>(&query, &[tokens.into()]).unwrap();
+        usize::try_from(count).unwrap()
+    }
 
-        let mut token_map = HashMap::new();
+    fn apply_batch_with_offsets(&self, tokens: Vec<String>) -> Vec<Vec<(u32, Range<usize>)>> {
+        let token_map = self.lookup(&tokens);
+        tokens
+            .into_iter()
+            .map(|token| match token_map.get(&token) {
+                Some(id) => vec![(*id, 0..token.len())],
+                None => vec![],
+            })
+            .collect()
+    }
+
+    fn apply_batches(&self, batches: Vec<Vec<String>>) -> Vec<Vec<u32>> {
+        let mut tokens = batches.iter().flatten().cloned().collect::<Vec<_>>();
+        tokens.sort();
+        tokens.dedup();
+        let token_map = self.lookup(&tokens);
+        batches
+            .into_iter()
+            .map(|tokens| {
+                tokens
+                    .into_iter()
+                    .filter_map(|token| token_map.get(&token).copied())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn vocab_size(&self) -> Option<usize> {
+        let query = format!(
+            r#"SELECT count(*) FROM tokenizer_catalog."model_{}""#,
+            self.name
+        );
+
+        spi_get_one::<i64>(&query, &[]).map(|count| usize::try_from(count).unwrap())
+    }
+
+    fn decodable(&self) -> bool {
+        true
+    }
+
+    fn decode(&self, ids: &[u32]) -> String {
+        let query = format!(
+            r#"SELECT id, token FROM tokenizer_catalog."model_{}" WHERE id = ANY($1)"#,
+            self.name
+        );
+        let ids = ids
+            .iter()
+            .map(|id| i32::try_from(*id).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut id_map = HashMap::new();
         pgrx::Spi::connect(|client| {
             let tuptable = client
-                .select(&query, None, &[tokens.clone().into()])
+                .select(&query, None, &[ids.clone().into()])
                 .unwrap_or_report();
             for tup in tuptable {
                 let id: i32 = tup.get(1).unwrap_or_report().expect("no id value");
-                let id = u32::try_from(id).expect("id is not a valid u32");
                 let token: String = tup.get(2).unwrap_or_report().expect("no token value");
-                token_map.insert(token, id);
+                id_map.insert(id, token);
             }
         });
 
-        tokens
-            .into_iter()
-            .filter_map(|token| token_map.get(&token).copied())
-            .collect()
+        ids.iter()
+            .filter_map(|id| id_map.get(id).cloned())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn id_to_token(&self, id: u32) -> Option<String> {
+        let query = format!(
+            r#"SELECT token FROM tokenizer_catalog."model_{}" WHERE id = $1"#,
+            self.name
+        );
+        spi_get_one::<String>(&query, &[i32::try_from(id).ok()?.into()])
+    }
+
+    fn token_to_id(&self, token: &str) -> Option<u32> {
+        let query = format!(
+            r#"SELECT id FROM tokenizer_catalog."model_{}" WHERE token = $1"#,
+            self.name
+        );
+        spi_get_one::<i32>(&query, &[token.into()]).map(|id| u32::try_from(id).unwrap())
     }
 }
 
@@ -138,6 +239,7 @@ fn create_custom_model(name: &str, config: &str) {
         ON CONFLICT (name) DO NOTHING RETURNING 1
         "#;
     let custom_model = CustomModel::new(name, &config);
+    let dependencies = [(Catalog::TextAnalyzer, config.text_analyzer.clone())];
     let config_str = serde_json::to_string(&ModelConfig::Custom(config)).unwrap();
 
     pgrx::Spi::connect_mut(|client| {
@@ -154,14 +256,18 @@ fn create_custom_model(name: &str, config: &str) {
 
         MODEL_OBJECT_POOL.insert(name.to_string(), Arc::new(custom_model));
     });
+
+    record_dependencies(Catalog::Model, name, &dependencies);
+    invalidate_catalog(Catalog::Model);
 }
 
 #[pgrx::pg_extern(volatile, parallel_safe)]
-fn drop_custom_model(name: &str) {
+fn drop_custom_model(name: &str, cascade: default!(bool, false)) {
     if let Err(e) = validate_new_model_name(name) {
         pgrx::warning!("Invalid model name: {}, Details: {}", name, e);
         return;
     }
+    drop_dependents(Catalog::Model, name, cascade);
 
     let select_config = r#"SELECT config FROM tokenizer_catalog.model WHERE name = $1"#;
     let config_bytes: &str = spi_get_one(select_config, &[name.into()]).unwrap();
@@ -194,6 +300,33 @@ fn drop_custom_model(name: &str) {
     });
 
     MODEL_OBJECT_POOL.remove(name);
+    remove_dependencies(Catalog::Model, name);
+    invalidate_catalog(Catalog::Model);
+}
+
+// the vocabulary of a custom model is built by its text analyzer, existing tokens are not
+// updated when the text analyzer or its dictionaries change
+pub fn warn_stale_custom_models(catalog: Catalog, name: &str) {
+    let text_analyzers = match catalog {
+        Catalog::TextAnalyzer => vec![name.to_string()],
+        _ => dependents(catalog, name)
+            .into_iter()
+            .filter(|(kind, _)| *kind == Catalog::TextAnalyzer)
+            .map(|(_, name)| name)
+            .collect(),
+    };
+
+    for text_analyzer in text_analyzers {
+        for (kind, model) in dependents(Catalog::TextAnalyzer, &text_analyzer) {
+            if kind == Catalog::Model {
+                pgrx::warning!(
+                    "Custom model {} uses text analyzer {}, its vocabulary should be rebuilt by dropping and recreating the model",
+                    model,
+                    text_analyzer
+                );
+            }
+        }
+    }
 }
 
 const MAX_TOKEN_LENGTH: usize = 2600;
@@ -334,15 +467,6 @@ fn custom_model_tokenizer_set_target_column_trigger<'a>(
     Ok(Some(new))
 }
 
-fn quote_identifier(ident: &str) -> String {
-    unsafe {
-        let ptr = pgrx::pg_sys::quote_identifier(ident.as_pg_cstr());
-        let quoted_str = CStr::from_ptr(ptr).to_str().unwrap().to_string();
-        pgrx::pg_sys::pfree(ptr as _);
-        quoted_str
-    }
-}
-
 fn lookup_type_name(oid: pgrx::pg_sys::Oid) -> String {
     unsafe {
         // SAFETY: nothing to concern ourselves with other than just calling into Postgres FFI
@@ -1,8 +1,17 @@
-use std::sync::Arc;
+use std::{borrow::Cow, ops::Range, sync::Arc};
 
-use tokenizers::Tokenizer;
+use pgrx::{iter::TableIterator, name};
+use tokenizers::{
+    utils::truncation::truncate_encodings, PaddingDirection, PostProcessor, Tokenizer,
+    TruncationDirection, TruncationParams, TruncationStrategy,
+};
 
-use super::{validate_new_model_name, ModelConfig, TokenizerModel, MODEL_OBJECT_POOL};
+use crate::{
+    cache::{invalidate_catalog, Catalog},
+    dependency::drop_dependents,
+};
+
+use super::{get_model, validate_new_model_name, ModelConfig, TokenizerModel, MODEL_OBJECT_POOL};
 
 #[derive(Debug)]
 pub struct HuggingFaceModel {
@@ -24,6 +33,42 @@ impl TokenizerModel for HuggingFaceModel {
     fn apply_batch(&self, tokens: Vec<String>) -> Vec<u32> {
         self.tokenizer.apply_batch(tokens)
     }
+
+    fn count_batch(&self, tokens: Vec<String>) -> usize {
+        self.tokenizer.count_batch(tokens)
+    }
+
+    fn apply_with_offsets(&self, token: String) -> Vec<(u32, Range<usize>)> {
+        self.tokenizer.apply_with_offsets(token)
+    }
+
+    fn apply_batches(&self, batches: Vec<Vec<String>>) -> Vec<Vec<u32>> {
+        self.tokenizer.apply_batches(batches)
+    }
+
+    fn vocab_size(&self) -> Option<usize> {
+        Some(self.tokenizer.get_vocab_size(true))
+    }
+
+    fn decodable(&self) -> bool {
+        true
+    }
+
+    fn decode(&self, ids: &[u32]) -> String {
+        TokenizerModel::decode(&self.tokenizer, ids)
+    }
+
+    fn id_to_token(&self, id: u32) -> Option<String> {
+        TokenizerModel::id_to_token(&self.tokenizer, id)
+    }
+
+    fn token_to_id(&self, token: &str) -> Option<u32> {
+        TokenizerModel::token_to_id(&self.tokenizer, token)
+    }
+
+    fn huggingface(&self) -> Option<&Tokenizer> {
+        Some(&self.tokenizer)
+    }
 }
 
 pub type HuggingFaceConfig = String;
@@ -57,11 +102,14 @@ fn create_huggingface_model(name: &str, config: &str) {
             panic!("Model already exists: {}", name);
         }
     });
+
+    invalidate_catalog(Catalog::Model);
 }
 
 #[pgrx::pg_extern(volatile, parallel_safe)]
-fn drop_huggingface_model(name: &str) {
+fn drop_huggingface_model(name: &str, cascade: default!(bool, false)) {
     validate_new_model_name(name).unwrap();
+    drop_dependents(Catalog::Model, name, cascade);
 
     let delete_model = r#"
         DELETE FROM tokenizer_catalog.model WHERE name = $1 RETURNING 1
@@ -78,4 +126,131 @@ fn drop_huggingface_model(name: &str) {
     });
 
     MODEL_OBJECT_POOL.remove(name);
+    invalidate_catalog(Catalog::Model);
+}
+
+// the padding tokens of common vocabularies, for tokenizers without a padding config
+const PAD_TOKENS: &[&str] = &["[PAD]", "<pad>", "<|pad|>"];
+
+fn to_i32_vec<T: TryInto<i32>>(values: impl IntoIterator<Item = T>) -> Vec<i32> {
+    values
+        .into_iter()
+        .map(|v| {
+            v.try_into()
+                .unwrap_or_else(|_| panic!("Value is out of the range of int4"))
+        })
+        .collect()
+}
+
+// Prepare the inputs of a transformer model like the `__call__` of huggingface tokenizers. The
+// text and the pair are truncated before special tokens are added, so `max_length` includes them.
+// Offsets are character offsets in the text or the pair that a token comes from, and special
+// tokens and padding have zero offsets.
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn tokenize_hf(
+    text: &str,
+    model_name: &str,
+    pair: default!(Option<&str>, "NULL"),
+    add_special_tokens: default!(bool, true),
+    max_length: default!(Option<i32>, "NULL"),
+    truncation_side: default!(&str, "'right'"),
+    padding: default!(bool, false),
+) -> TableIterator<
+    'static,
+    (
+        name!(ids, Vec<i32>),
+        name!(type_ids, Vec<i32>),
+        name!(attention_mask, Vec<i32>),
+        name!(start_offsets, Vec<i32>),
+        name!(end_offsets, Vec<i32>),
+    ),
+> {
+    let model = get_model(model_name);
+    let Some(tokenizer) = model.huggingface() else {
+        panic!("Model {} is not a huggingface model", model_name);
+    };
+    // The truncation and padding of the tokenizer.json are applied by every encode, so they are
+    // removed from a copy to make the arguments the only source of them. The padding config is
+    // still where the padding token comes from.
+    let padding_params = tokenizer.get_padding().cloned();
+    let tokenizer = match (tokenizer.get_truncation(), tokenizer.get_padding()) {
+        (None, None) => Cow::Borrowed(tokenizer),
+        _ => {
+            let mut tokenizer = tokenizer.clone();
+            tokenizer.with_truncation(None).unwrap();
+            tokenizer.with_padding(None);
+            Cow::Owned(tokenizer)
+        }
+    };
+    let direction = match truncation_side {
+        "right" => TruncationDirection::Right,
+        "left" => TruncationDirection::Left,
+        _ => panic!(
+            "Unknown truncation_side: {}, expected one of right, left",
+            truncation_side
+        ),
+    };
+    let max_length = max_length.map(|length| {
+        usize::try_from(length).unwrap_or_else(|_| panic!("Invalid max_length: {}", length))
+    });
+    if padding && max_length.is_none() {
+        panic!("padding requires max_length");
+    }
+
+    let mut encoding = tokenizer.encode_char_offsets(text, false).unwrap();
+    let mut pair_encoding = pair.map(|pair| tokenizer.encode_char_offsets(pair, false).unwrap());
+    if let Some(max_length) = max_length {
+        let added = match tokenizer.get_post_processor() {
+            Some(processor) if add_special_tokens => processor.added_tokens(pair.is_some()),
+            _ => 0,
+        };
+        if max_length <= added {
+            panic!(
+                "max_length must be greater than the number of special tokens: {}",
+                added
+            );
+        }
+        let params = TruncationParams {
+            max_length: max_length - added,
+            strategy: TruncationStrategy::LongestFirst,
+            stride: 0,
+            direction,
+        };
+        (encoding, pair_encoding) = truncate_encodings(encoding, pair_encoding, &params).unwrap();
+    }
+    let mut encoding = tokenizer
+        .post_process(encoding, pair_encoding, add_special_tokens)
+        .unwrap();
+
+    if let Some(max_length) = max_length.filter(|_| padding) {
+        let (pad_id, pad_type_id, pad_token) = match padding_params {
+            Some(params) => (params.pad_id, params.pad_type_id, params.pad_token),
+            None => {
+                let Some((pad_token, pad_id)) = PAD_TOKENS
+                    .iter()
+                    .find_map(|token| Some((token.to_string(), tokenizer.token_to_id(token)?)))
+                else {
+                    panic!("Model {} has no padding token", model_name);
+                };
+                (pad_id, 0, pad_token)
+            }
+        };
+        encoding.pad(
+            max_length,
+            pad_id,
+            pad_type_id,
+            &pad_token,
+            PaddingDirection::Right,
+        );
+    }
+
+    let (start_offsets, end_offsets): (Vec<_>, Vec<_>) =
+        encoding.get_offsets().iter().copied().unzip();
+    TableIterator::once((
+        to_i32_vec(encoding.get_ids().iter().copied()),
+        to_i32_vec(encoding.get_type_ids().iter().copied()),
+        to_i32_vec(encoding.get_attention_mask().iter().copied()),
+        to_i32_vec(start_offsets),
+        to_i32_vec(end_offsets),
+    ))
 }
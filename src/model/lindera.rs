@@ -3,6 +3,11 @@ use std::sync::Arc;
 use lindera::tokenizer::{Tokenizer, TokenizerConfig};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    cache::{invalidate_catalog, Catalog},
+    dependency::drop_dependents,
+};
+
 use super::{validate_new_model_name, ModelConfig, TokenizerModel, MODEL_OBJECT_POOL};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -59,11 +64,14 @@ fn create_lindera_model(name: &str, config: &str) {
 
         MODEL_OBJECT_POOL.insert(name.to_string(), Arc::new(lindera_model));
     });
+
+    invalidate_catalog(Catalog::Model);
 }
 
 #[pgrx::pg_extern(volatile, parallel_safe)]
-fn drop_lindera_model(name: &str) {
+fn drop_lindera_model(name: &str, cascade: default!(bool, false)) {
     validate_new_model_name(name).unwrap();
+    drop_dependents(Catalog::Model, name, cascade);
 
     let delete_model = r#"
         DELETE FROM tokenizer_catalog.model WHERE name = $1 RETURNING 1
@@ -80,4 +88,5 @@ fn drop_lindera_model(name: &str) {
     });
 
     MODEL_OBJECT_POOL.remove(name);
+    invalidate_catalog(Catalog::Model);
 }
@@ -3,16 +3,25 @@ mod custom;
 mod huggingface;
 mod lindera;
 
-use std::sync::{Arc, LazyLock};
-
-use builtin::{get_builtin_model, is_builtin_model};
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::{Arc, LazyLock},
+};
+
+pub use builtin::is_builtin_model;
+use builtin::{builtin_vocab_size, get_builtin_model, BUILTIN_MODELS};
+pub use custom::warn_stale_custom_models;
 use custom::{CustomModel, CustomModelConfig};
-use dashmap::{DashMap, Entry};
+use dashmap::DashMap;
 use huggingface::{HuggingFaceConfig, HuggingFaceModel};
 use lindera::{LinderaConfig, LinderaModel};
 use serde::{Deserialize, Serialize};
 
-use crate::utils::spi_get_one;
+use crate::{
+    cache::{watch_catalog, Catalog},
+    utils::spi_get_one,
+};
 
 pub trait TokenizerModel {
     fn apply(&self, token: String) -> Vec<u32>;
@@ -23,6 +32,66 @@ pub trait TokenizerModel {
             .flat_map(|token| self.apply(token))
             .collect()
     }
+
+    // the number of token ids of `apply_batch`
+    fn count_batch(&self, tokens: Vec<String>) -> usize {
+        self.apply_batch(tokens).len()
+    }
+
+    // the token ids with the byte range in the token they come from, the whole token by default
+    fn apply_with_offsets(&self, token: String) -> Vec<(u32, Range<usize>)> {
+        let len = token.len();
+        self.apply(token)
+            .into_iter()
+            .map(|id| (id, 0..len))
+            .collect()
+    }
+
+    fn apply_batch_with_offsets(&self, tokens: Vec<String>) -> Vec<Vec<(u32, Range<usize>)>> {
+        tokens
+            .into_iter()
+            .map(|token| self.apply_with_offsets(token))
+            .collect()
+    }
+
+    // the token ids of each batch, models looking up the vocabulary can do it once for all
+    fn apply_batches(&self, batches: Vec<Vec<String>>) -> Vec<Vec<u32>> {
+        batches
+            .into_iter()
+            .map(|tokens| self.apply_batch(tokens))
+            .collect()
+    }
+
+    // `None` if the model does not expose its vocabulary
+    fn vocab_size(&self) -> Option<usize> {
+        None
+    }
+
+    // whether the model supports `decode`, `id_to_token` and `token_to_id`
+    fn decodable(&self) -> bool {
+        false
+    }
+
+    // tokens joined by spaces, unknown ids are skipped
+    fn decode(&self, ids: &[u32]) -> String {
+        ids.iter()
+            .filter_map(|id| self.id_to_token(*id))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn id_to_token(&self, _id: u32) -> Option<String> {
+        None
+    }
+
+    fn token_to_id(&self, _token: &str) -> Option<u32> {
+        None
+    }
+
+    // the inner tokenizer of huggingface models, for features beyond tokenizing words
+    fn huggingface(&self) -> Option<&tokenizers::Tokenizer> {
+        None
+    }
 }
 pub type TokenizerModelPtr = Arc<dyn TokenizerModel + Send + Sync>;
 
@@ -30,11 +99,99 @@ impl TokenizerModel for tokenizers::Tokenizer {
     fn apply(&self, token: String) -> Vec<u32> {
         self.encode_fast(token, false).unwrap().get_ids().to_vec()
     }
+
+    fn count_batch(&self, tokens: Vec<String>) -> usize {
+        tokens
+            .into_iter()
+            .map(|token| self.encode_fast(token, false).unwrap().len())
+            .sum()
+    }
+
+    // `encode_fast` does not compute the offsets
+    fn apply_with_offsets(&self, token: String) -> Vec<(u32, Range<usize>)> {
+        let encoding = self.encode(token, false).unwrap();
+        encoding
+            .get_ids()
+            .iter()
+            .zip(encoding.get_offsets())
+            .map(|(id, (start, end))| (*id, *start..*end))
+            .collect()
+    }
+
+    fn vocab_size(&self) -> Option<usize> {
+        Some(self.get_vocab_size(true))
+    }
+
+    fn decodable(&self) -> bool {
+        true
+    }
+
+    // Special tokens like `[CLS]` are skipped. The methods of the inner tokenizer are called
+    // through deref, since they are shadowed by the methods of this trait.
+    fn decode(&self, ids: &[u32]) -> String {
+        (**self).decode(ids, true).unwrap()
+    }
+
+    fn id_to_token(&self, id: u32) -> Option<String> {
+        (**self).id_to_token(id)
+    }
+
+    fn token_to_id(&self, token: &str) -> Option<u32> {
+        (**self).token_to_id(token)
+    }
+
+    fn huggingface(&self) -> Option<&tokenizers::Tokenizer> {
+        Some(self)
+    }
 }
 
-impl TokenizerModel for tocken::tokenizer::Tokenizer {
+// tocken does not expose its vocabulary, so it is read from the json of the tokenizer
+pub struct TockenModel {
+    tokenizer: tocken::tokenizer::Tokenizer,
+    vocab: HashMap<String, u32>,
+    tokens: HashMap<u32, String>,
+}
+
+impl TockenModel {
+    pub fn loads(json: &str) -> Self {
+        #[derive(Deserialize)]
+        struct TockenVocab {
+            table: HashMap<String, u32>,
+        }
+
+        let tokenizer = tocken::tokenizer::Tokenizer::loads(json);
+        let vocab = serde_json::from_str::<TockenVocab>(json).unwrap().table;
+        let tokens = vocab
+            .iter()
+            .map(|(token, id)| (*id, token.clone()))
+            .collect();
+        TockenModel {
+            tokenizer,
+            vocab,
+            tokens,
+        }
+    }
+}
+
+impl TokenizerModel for TockenModel {
     fn apply(&self, token: String) -> Vec<u32> {
-        self.tokenize(&token)
+        self.tokenizer.tokenize(&token)
+    }
+
+    fn vocab_size(&self) -> Option<usize> {
+        Some(self.vocab.len())
+    }
+
+    fn decodable(&self) -> bool {
+        true
+    }
+
+    fn id_to_token(&self, id: u32) -> Option<String> {
+        self.tokens.get(&id).cloned()
+    }
+
+    fn token_to_id(&self, token: &str) -> Option<u32> {
+        self.vocab.get(token).copied()
     }
 }
 
@@ -47,6 +204,16 @@ enum ModelConfig {
     HuggingFace(HuggingFaceConfig),
 }
 
+impl ModelConfig {
+    fn kind(&self) -> &'static str {
+        match self {
+            ModelConfig::Custom(_) => "custom",
+            ModelConfig::Lindera(_) => "lindera",
+            ModelConfig::HuggingFace(_) => "huggingface",
+        }
+    }
+}
+
 type ModelObjectPool = DashMap<String, TokenizerModelPtr>;
 pub(super) static MODEL_OBJECT_POOL: LazyLock<ModelObjectPool> =
     LazyLock::new(ModelObjectPool::default);
@@ -67,21 +234,36 @@ pub fn get_model(name: &str) -> TokenizerModelPtr {
         return model.clone();
     }
 
-    match MODEL_OBJECT_POOL.entry(name.to_string()) {
-        Entry::Occupied(entry) => entry.get().clone(),
-        Entry::Vacant(entry) => {
-            if let Some(object) = get_builtin_model(name) {
-                entry.insert(object.clone());
-                return object;
-            }
+    // the entry lock is not held while loading, since the pool may be cleared by the relcache
+    // callback during the query
+    let object = match get_builtin_model(name) {
+        Some(object) => object,
+        None => {
+            watch_catalog(Catalog::Model);
+            let Some(object) = get_model_from_database(name) else {
+                panic!("Model not found: {}", name);
+            };
+            object
+        }
+    };
+    MODEL_OBJECT_POOL.insert(name.to_string(), object.clone());
+    object
+}
 
-            if let Some(object) = get_model_from_database(name) {
-                entry.insert(object.clone());
-                return object;
-            }
+// builtin models never change, and they are expensive to load
+pub fn invalidate_models() {
+    MODEL_OBJECT_POOL.retain(|name, _| is_builtin_model(name));
+}
 
-            panic!("Model not found: {}", name);
-        }
+// one of `builtin`, `custom`, `lindera` and `huggingface`
+pub fn get_model_kind(name: &str) -> &'static str {
+    if is_builtin_model(name) {
+        return "builtin";
+    }
+    watch_catalog(Catalog::Model);
+    match get_model_config(name) {
+        Some(config) => config.kind(),
+        None => panic!("Model not found: {}", name),
     }
 }
 
@@ -136,6 +318,40 @@ pub fn validate_new_model_name(name: &str) -> Result<(), String> {
     Ok(())
 }
 
+#[pgrx::pg_extern(stable, parallel_safe)]
+pub fn model_kind(name: &str) -> &'static str {
+    get_model_kind(name)
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+pub fn model_vocab_size(name: &str) -> Option<i32> {
+    get_model(name)
+        .vocab_size()
+        .map(|size| i32::try_from(size).unwrap())
+}
+
+// the vocabulary size of the `models` view, without loading the model. Builtin models have
+// a known size and custom models count their vocabulary table, while lindera and huggingface
+// models are `NULL`, since their vocabulary is only known once loaded.
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn _pg_tokenizer_stored_vocab_size(name: &str) -> Option<i32> {
+    if is_builtin_model(name) {
+        return builtin_vocab_size(name);
+    }
+    match get_model_config(name)? {
+        ModelConfig::Custom(_) => {
+            let query = format!(r#"SELECT count(*) FROM tokenizer_catalog."model_{}""#, name);
+            spi_get_one::<i64>(&query, &[]).map(|count| i32::try_from(count).unwrap())
+        }
+        ModelConfig::Lindera(_) | ModelConfig::HuggingFace(_) => None,
+    }
+}
+
+#[pgrx::pg_extern(immutable, parallel_safe)]
+pub fn list_builtin_models() -> Vec<String> {
+    BUILTIN_MODELS.iter().map(|name| name.to_string()).collect()
+}
+
 #[pgrx::pg_extern(volatile, parallel_unsafe)]
 pub fn add_preload_model(name: &str) {
     validate_model_name(name).unwrap();
@@ -254,3 +470,26 @@ pub fn init() {
 //     };
 //     Ok(Some(build_model(name, &config)))
 // }
+
+fn get_decodable_model(name: &str) -> TokenizerModelPtr {
+    let model = get_model(name);
+    if !model.decodable() {
+        panic!("Model {} does not support decoding", name);
+    }
+    model
+}
+
+#[pgrx::pg_extern(stable, parallel_safe)]
+pub fn id_to_token(id: i32, model_name: &str) -> Option<String> {
+    let model = get_decodable_model(model_name);
+    let id = u32::try_from(id).ok()?;
+    model.id_to_token(id)
+}
+
+#[pgrx::pg_extern(stable, parallel_safe)]
+pub fn token_to_id(token: &str, model_name: &str) -> Option<i32> {
+    let model = get_decodable_model(model_name);
+    model
+        .token_to_id(token)
+        .map(|id| i32::try_from(id).unwrap())
+}
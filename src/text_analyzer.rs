@@ -1,16 +1,22 @@
 use std::{
     borrow::Cow,
+    collections::HashSet,
     sync::{Arc, LazyLock},
 };
 
-use dashmap::{DashMap, Entry};
+use dashmap::DashMap;
+use pgrx::{iter::TableIterator, name, JsonB};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
 use crate::{
+    cache::{invalidate_catalog, watch_catalog, Catalog},
     character_filter::{get_character_filter, CharacterFilterConfig, CharacterFilterPtr},
+    dependency::{drop_dependents, record_dependencies, remove_dependencies},
+    model::warn_stale_custom_models,
     pre_tokenizer::{get_pre_tokenizer, PreTokenizerConfig, PreTokenizerPtr},
-    token_filter::{get_token_filter, TokenFilterConfig, TokenFilterPtr},
+    token_filter::{get_token_filter, Span, TokenFilterConfig, TokenFilterPtr},
+    tokenizer::invalidate_tokenizers,
     utils::spi_get_one,
 };
 
@@ -26,27 +32,67 @@ pub struct TextAnalyzerConfig {
 }
 
 pub struct TextAnalyzer {
+    pub config: TextAnalyzerConfig,
     pub character_filters: Vec<CharacterFilterPtr>,
     pub pre_tokenizer: Option<PreTokenizerPtr>,
     pub token_filters: Vec<TokenFilterPtr>,
 }
 pub type TextAnalyzerPtr = Arc<TextAnalyzer>;
 
+// the output of a stage, character filters output the whole text
+pub enum StageOutput<'a> {
+    Text(&'a str),
+    Tokens(&'a [String]),
+}
+
+// the kind, config and output of a stage
+pub type ExplainedStage = (&'static str, serde_json::Value, Vec<String>);
+
+impl TextAnalyzerConfig {
+    pub fn dependencies(&self) -> Vec<(Catalog, String)> {
+        self.token_filters
+            .iter()
+            .filter_map(TokenFilterConfig::dependency)
+            .collect()
+    }
+
+    // the kind and config of each stage, in the order of application
+    pub fn stages(&self) -> Vec<(&'static str, serde_json::Value)> {
+        let mut stages = Vec::new();
+        for filter in &self.character_filters {
+            stages.push(("character_filter", serde_json::to_value(filter).unwrap()));
+        }
+        if let Some(pre_tokenizer) = &self.pre_tokenizer {
+            stages.push((
+                "pre_tokenizer",
+                serde_json::to_value(pre_tokenizer).unwrap(),
+            ));
+        }
+        for filter in &self.token_filters {
+            stages.push(("token_filter", serde_json::to_value(filter).unwrap()));
+        }
+        stages
+    }
+}
+
 impl TextAnalyzer {
     pub fn build(config: TextAnalyzerConfig) -> Self {
         let character_filters = config
             .character_filters
-            .into_iter()
+            .iter()
+            .cloned()
             .map(get_character_filter)
             .collect();
-        let pre_tokenizer = config.pre_tokenizer.map(get_pre_tokenizer);
+        let pre_tokenizer = config.pre_tokenizer.clone().map(get_pre_tokenizer);
         let token_filters = config
             .token_filters
-            .into_iter()
+            .iter()
+            .cloned()
             .map(get_token_filter)
             .collect();
 
         TextAnalyzer {
+            config,
             character_filters,
             pre_tokenizer,
             token_filters,
@@ -54,9 +100,15 @@ impl TextAnalyzer {
     }
 
     pub fn apply(&self, text: &str) -> Vec<String> {
+        self.apply_traced(text, |_| {})
+    }
+
+    // `trace` is called with the output of every stage
+    pub fn apply_traced(&self, text: &str, mut trace: impl FnMut(StageOutput)) -> Vec<String> {
         let mut text = Cow::Borrowed(text);
         for filter in &self.character_filters {
             filter.apply(&mut text);
+            trace(StageOutput::Text(&text));
         }
 
         let text = text.as_ref();
@@ -68,13 +120,127 @@ impl TextAnalyzer {
             .into_iter()
             .map(|s| s.to_string())
             .collect::<Vec<_>>();
+        if self.pre_tokenizer.is_some() {
+            trace(StageOutput::Tokens(&tokens));
+        }
 
+        let mut keywords = Vec::new();
         for filter in &self.token_filters {
-            tokens = filter.apply_batch(tokens);
+            tokens = if filter.skip_keywords() && !keywords.is_empty() {
+                apply_skipping_keywords(tokens, &keywords, String::as_str, |tokens| {
+                    filter.apply_batch(tokens)
+                })
+            } else {
+                filter.apply_batch(tokens)
+            };
+            if let Some(set) = filter.keywords() {
+                keywords.push(set);
+            }
+            trace(StageOutput::Tokens(&tokens));
         }
 
         tokens
     }
+
+    // Like `apply`, and each token comes with its span in `text`. Character filters are applied
+    // with offset maps to correct the offsets, see `CharacterFilter::apply_with_offsets`.
+    pub fn apply_with_spans(&self, text: &str) -> Vec<(String, Span)> {
+        let mut filtered = Cow::Borrowed(text);
+        let mut maps = Vec::with_capacity(self.character_filters.len());
+        for filter in &self.character_filters {
+            let (text, map) = filter.apply_with_offsets(&filtered);
+            filtered = Cow::Owned(text);
+            maps.push(map);
+        }
+
+        let filtered = filtered.as_ref();
+        let pre_tokenized = match &self.pre_tokenizer {
+            Some(pre_tokenizer) => pre_tokenizer.pre_tokenize(filtered),
+            None => vec![filtered],
+        };
+        let mut tokens = pre_tokenized
+            .into_iter()
+            .enumerate()
+            .map(|(position, s)| {
+                // pre-tokenized tokens are slices of the filtered text
+                let start = s.as_ptr() as usize - filtered.as_ptr() as usize;
+                let mut span = Span {
+                    position,
+                    start,
+                    end: start + s.len(),
+                };
+                for map in maps.iter().rev() {
+                    span.start = map.start(span.start);
+                    span.end = map.end(span.end);
+                }
+                (s.to_string(), span)
+            })
+            .collect::<Vec<_>>();
+
+        let mut keywords = Vec::new();
+        for filter in &self.token_filters {
+            tokens = if filter.skip_keywords() && !keywords.is_empty() {
+                apply_skipping_keywords(
+                    tokens,
+                    &keywords,
+                    |(token, _)| token.as_str(),
+                    |tokens| filter.apply_batch_with_spans(tokens),
+                )
+            } else {
+                filter.apply_batch_with_spans(tokens)
+            };
+            if let Some(set) = filter.keywords() {
+                keywords.push(set);
+            }
+        }
+
+        tokens
+    }
+
+    pub fn explain(&self, text: &str) -> Vec<ExplainedStage> {
+        let mut outputs = Vec::new();
+        self.apply_traced(text, |output| {
+            outputs.push(match output {
+                StageOutput::Text(text) => vec![text.to_string()],
+                StageOutput::Tokens(tokens) => tokens.to_vec(),
+            })
+        });
+
+        self.config
+            .stages()
+            .into_iter()
+            .zip(outputs)
+            .map(|((kind, config), output)| (kind, config, output))
+            .collect()
+    }
+}
+
+// keep the order of tokens, only apply the filter to consecutive non-keyword tokens
+fn apply_skipping_keywords<T>(
+    tokens: Vec<T>,
+    keywords: &[&HashSet<String>],
+    word: impl Fn(&T) -> &str,
+    apply_batch: impl Fn(Vec<T>) -> Vec<T>,
+) -> Vec<T> {
+    let is_keyword = |token: &T| keywords.iter().any(|set| set.contains(word(token)));
+
+    let mut results = Vec::with_capacity(tokens.len());
+    let mut pending = Vec::new();
+    for token in tokens {
+        if is_keyword(&token) {
+            if !pending.is_empty() {
+                results.extend(apply_batch(std::mem::take(&mut pending)));
+            }
+            results.push(token);
+        } else {
+            pending.push(token);
+        }
+    }
+    if !pending.is_empty() {
+        results.extend(apply_batch(pending));
+    }
+
+    results
 }
 
 pgrx::extension_sql!(
@@ -92,30 +258,43 @@ static TEXT_ANALYZER_OBJECT_POOL: LazyLock<TextAnalyzerObjectPool> =
     LazyLock::new(TextAnalyzerObjectPool::default);
 
 pub fn get_text_analyzer(name: &str) -> TextAnalyzerPtr {
+    let Some(object) = find_text_analyzer(name) else {
+        panic!("TextAnalyzer not found: {}", name);
+    };
+    object
+}
+
+// like `get_text_analyzer`, but returns `None` if it does not exist
+pub fn find_text_analyzer(name: &str) -> Option<TextAnalyzerPtr> {
     if let Some(model) = TEXT_ANALYZER_OBJECT_POOL.get(name) {
-        return model.clone();
+        return Some(model.clone());
     }
 
-    match TEXT_ANALYZER_OBJECT_POOL.entry(name.to_string()) {
-        Entry::Occupied(entry) => entry.get().clone(),
-        Entry::Vacant(entry) => {
-            if let Some(object) = get_text_analyzer_from_database(name) {
-                entry.insert(object.clone());
-                return object;
-            }
+    // the entry lock is not held while loading, since the pool may be cleared by the relcache
+    // callback during the query
+    watch_catalog(Catalog::TextAnalyzer);
+    let object = get_text_analyzer_from_database(name)?;
+    TEXT_ANALYZER_OBJECT_POOL.insert(name.to_string(), object.clone());
+    Some(object)
+}
 
-            panic!("TextAnalyzer not found: {}", name);
-        }
-    }
+// text analyzers hold their token filters, so they should be rebuilt when any of them changes
+pub fn invalidate_text_analyzers() {
+    TEXT_ANALYZER_OBJECT_POOL.clear();
+    invalidate_tokenizers();
 }
 
-fn get_text_analyzer_from_database(name: &str) -> Option<TextAnalyzerPtr> {
+pub fn get_text_analyzer_config(name: &str) -> Option<TextAnalyzerConfig> {
     let config: &str = spi_get_one(
         "SELECT config FROM tokenizer_catalog.text_analyzer WHERE name = $1",
         &[name.into()],
     )?;
 
-    let config: TextAnalyzerConfig = serde_json::from_str(config).unwrap();
+    Some(serde_json::from_str(config).unwrap())
+}
+
+fn get_text_analyzer_from_database(name: &str) -> Option<TextAnalyzerPtr> {
+    let config = get_text_analyzer_config(name)?;
     Some(Arc::new(TextAnalyzer::build(config)))
 }
 
@@ -125,6 +304,7 @@ fn create_text_analyzer(name: &str, config: &str) {
     config.validate().unwrap();
 
     let config_str = serde_json::to_string(&config).unwrap();
+    let dependencies = config.dependencies();
     let text_analyzer = TextAnalyzer::build(config);
 
     pgrx::Spi::connect_mut(|client| {
@@ -145,10 +325,47 @@ fn create_text_analyzer(name: &str, config: &str) {
 
         TEXT_ANALYZER_OBJECT_POOL.insert(name.to_string(), Arc::new(text_analyzer));
     });
+
+    record_dependencies(Catalog::TextAnalyzer, name, &dependencies);
+    invalidate_catalog(Catalog::TextAnalyzer);
 }
 
 #[pgrx::pg_extern(volatile, parallel_safe)]
-fn drop_text_analyzer(name: &str) {
+fn alter_text_analyzer(name: &str, config: &str) {
+    let config: TextAnalyzerConfig = toml::from_str(config).unwrap();
+    config.validate().unwrap();
+
+    let config_str = serde_json::to_string(&config).unwrap();
+    let dependencies = config.dependencies();
+    let text_analyzer = TextAnalyzer::build(config);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                "UPDATE tokenizer_catalog.text_analyzer SET config = $2 WHERE name = $1 RETURNING 1",
+                Some(1),
+                &[name.into(), config_str.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            panic!("TextAnalyzer not found: {}", name);
+        }
+    });
+
+    TEXT_ANALYZER_OBJECT_POOL.insert(name.to_string(), Arc::new(text_analyzer));
+    // tokenizers hold the text analyzer, so they are rebuilt too
+    invalidate_tokenizers();
+    warn_stale_custom_models(Catalog::TextAnalyzer, name);
+
+    record_dependencies(Catalog::TextAnalyzer, name, &dependencies);
+    invalidate_catalog(Catalog::TextAnalyzer);
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn drop_text_analyzer(name: &str, cascade: default!(bool, false)) {
+    drop_dependents(Catalog::TextAnalyzer, name, cascade);
+
     pgrx::Spi::connect_mut(|client| {
         let tuptable = client
             .update(
@@ -164,6 +381,8 @@ fn drop_text_analyzer(name: &str) {
     });
 
     TEXT_ANALYZER_OBJECT_POOL.remove(name);
+    remove_dependencies(Catalog::TextAnalyzer, name);
+    invalidate_catalog(Catalog::TextAnalyzer);
 }
 
 #[pgrx::pg_extern(immutable, parallel_safe)]
@@ -171,3 +390,79 @@ fn apply_text_analyzer(text: &str, text_analyzer_name: &str) -> Vec<String> {
     let text_analyzer = get_text_analyzer(text_analyzer_name);
     text_analyzer.apply(text)
 }
+
+// Like `apply_text_analyzer`, a row for each token numbered from 1 like `WITH ORDINALITY`.
+#[pgrx::pg_extern(immutable, parallel_safe)]
+fn apply_text_analyzer_srf(
+    text: &str,
+    text_analyzer_name: &str,
+) -> TableIterator<'static, (name!(ordinality, i32), name!(token, String))> {
+    let text_analyzer = get_text_analyzer(text_analyzer_name);
+    TableIterator::new(
+        text_analyzer
+            .apply(text)
+            .into_iter()
+            .enumerate()
+            .map(|(i, token)| (i as i32 + 1, token)),
+    )
+}
+
+// Each row is a stage of the text analyzer, with the text or tokens after it.
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn explain_text_analyzer(
+    text: &str,
+    text_analyzer_name: &str,
+) -> TableIterator<
+    'static,
+    (
+        name!(stage, i32),
+        name!(kind, String),
+        name!(config, JsonB),
+        name!(output, Vec<String>),
+    ),
+> {
+    let text_analyzer = get_text_analyzer(text_analyzer_name);
+    TableIterator::new(
+        text_analyzer
+            .explain(text)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (kind, config, output))| {
+                (i as i32 + 1, kind.to_string(), JsonB(config), output)
+            }),
+    )
+}
+
+// Offsets are character offsets in `text`, the token is `substr(text, start_offset + 1,
+// end_offset - start_offset)` before filtering.
+#[pgrx::pg_extern(immutable, parallel_safe)]
+fn apply_text_analyzer_with_offsets(
+    text: &str,
+    text_analyzer_name: &str,
+) -> TableIterator<
+    'static,
+    (
+        name!(token, String),
+        name!(position, i32),
+        name!(start_offset, i32),
+        name!(end_offset, i32),
+    ),
+> {
+    let text_analyzer = get_text_analyzer(text_analyzer_name);
+    let boundaries = text.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+    let char_offset = |offset: usize| boundaries.partition_point(|b| *b < offset) as i32;
+
+    let rows = text_analyzer
+        .apply_with_spans(text)
+        .into_iter()
+        .map(|(token, span)| {
+            (
+                token,
+                span.position as i32,
+                char_offset(span.start),
+                char_offset(span.end),
+            )
+        })
+        .collect::<Vec<_>>();
+    TableIterator::new(rows)
+}
@@ -0,0 +1,458 @@
+// Text analyzers as PostgreSQL text search configurations. The `tokenizer_catalog.analyzed`
+// parser splits a text into sentences, and each configuration maps them to its own dictionary of
+// the `tokenizer_catalog.analyzed` template, which applies the text analyzer named in the options
+// of the dictionary. The parser and the template are shipped with the extension, so creating a
+// configuration does not require superuser.
+
+use std::{collections::BTreeMap, ffi::c_char, ops::Range};
+
+use pgrx::{pg_sys, Internal, PgList, PgMemoryContexts};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    cache::Catalog,
+    dependency::{drop_dependents, record_dependencies, remove_dependencies},
+    text_analyzer::{get_text_analyzer, TextAnalyzer},
+    utils::{quote_identifier, quote_literal},
+};
+
+pgrx::extension_sql!(
+    r#"
+CREATE TABLE tokenizer_catalog.text_search_config (
+    name TEXT NOT NULL UNIQUE PRIMARY KEY,
+    text_analyzer TEXT NOT NULL,
+    -- the schema qualified name of the configuration and its dictionary
+    qualified_name TEXT NOT NULL
+);
+"#,
+    name = "text_search_config_table"
+);
+
+// the types of the default parser are reused, since `prsd_headline` treats spaces by the type
+const TEXT_TOKEN_TYPE: i32 = 1;
+const BLANK_TOKEN_TYPE: i32 = 12;
+
+// tokens of PostgreSQL text search should be shorter than `MAXSTRLEN`
+const MAX_SEGMENT_LEN: usize = 2046;
+
+// Split the text into sentences and the spaces between them. Segments are cut at spaces, or at
+// characters if there is no space, to fit in `MAX_SEGMENT_LEN`.
+fn segment(text: &str) -> Vec<(i32, Range<usize>)> {
+    let mut segments = Vec::new();
+    let mut push = |kind: i32, range: Range<usize>| {
+        let mut start = range.start;
+        while start < range.end {
+            let mut end = range.end.min(start + MAX_SEGMENT_LEN);
+            if end < range.end {
+                end = match text[start..end].rfind(char::is_whitespace) {
+                    Some(i) if i > 0 => start + i,
+                    _ => (start + 1..=end)
+                        .rev()
+                        .find(|i| text.is_char_boundary(*i))
+                        .unwrap(),
+                };
+            }
+            segments.push((kind, start..end));
+            start = end;
+        }
+    };
+
+    for (start, sentence) in text.split_sentence_bound_indices() {
+        let end = start + sentence.trim_end().len();
+        push(TEXT_TOKEN_TYPE, start..end);
+        push(BLANK_TOKEN_TYPE, end..start + sentence.len());
+    }
+    segments
+}
+
+// Tokens grouped by the position of the pre-tokenized token they come from, in the order of the
+// text. Tokens at the same position are alternatives like synonyms, and positions without tokens
+// like stopwords are removed.
+fn position_groups(text_analyzer: &TextAnalyzer, text: &str) -> Vec<Vec<String>> {
+    let mut positions = BTreeMap::<usize, Vec<String>>::new();
+    for (token, span) in text_analyzer.apply_with_spans(text) {
+        if token.is_empty() {
+            continue;
+        }
+        let tokens = positions.entry(span.position).or_default();
+        if !tokens.contains(&token) {
+            tokens.push(token);
+        }
+    }
+    positions.into_values().collect()
+}
+
+struct ParserState {
+    text: *const c_char,
+    segments: Vec<(i32, Range<usize>)>,
+    next: usize,
+}
+
+#[pgrx::pg_extern(immutable, parallel_safe)]
+fn text_search_parser_start(text: Internal, len: i32) -> Internal {
+    let text = unsafe { text.unwrap().unwrap().cast_mut_ptr::<c_char>() };
+    let segments = {
+        let bytes = unsafe { std::slice::from_raw_parts(text.cast::<u8>(), len as usize) };
+        segment(std::str::from_utf8(bytes).expect("Text is not valid UTF-8"))
+    };
+
+    // freed with the memory context of the parsing
+    let state = PgMemoryContexts::CurrentMemoryContext.leak_and_drop_on_delete(ParserState {
+        text,
+        segments,
+        next: 0,
+    });
+    Internal::from(Some(pg_sys::Datum::from(state)))
+}
+
+// returns the type of the token, or 0 at the end
+#[pgrx::pg_extern(immutable, parallel_safe)]
+fn text_search_parser_gettoken(state: Internal, token: Internal, len: Internal) -> Internal {
+    let token_type = unsafe {
+        let state = state.unwrap().unwrap().cast_mut_ptr::<ParserState>();
+        match (*state).segments.get((*state).next) {
+            Some((token_type, range)) => {
+                (*state).next += 1;
+                *token.unwrap().unwrap().cast_mut_ptr::<*const c_char>() =
+                    (*state).text.add(range.start);
+                *len.unwrap().unwrap().cast_mut_ptr::<i32>() = range.len() as i32;
+                *token_type
+            }
+            None => 0,
+        }
+    };
+    Internal::from(Some(pg_sys::Datum::from(token_type)))
+}
+
+#[pgrx::pg_extern(immutable, parallel_safe)]
+fn text_search_parser_end(_state: Internal) {}
+
+#[pgrx::pg_extern(immutable, parallel_safe)]
+fn text_search_parser_lextype(_arg: Internal) -> Internal {
+    let types = [
+        (
+            TEXT_TOKEN_TYPE,
+            c"text",
+            c"Text analyzed by the text analyzer",
+        ),
+        (BLANK_TOKEN_TYPE, c"blank", c"Space symbols"),
+    ];
+    unsafe {
+        // terminated by an entry with `lexid` 0
+        let descr = pg_sys::palloc0(std::mem::size_of::<pg_sys::LexDescr>() * (types.len() + 1))
+            .cast::<pg_sys::LexDescr>();
+        for (i, (lexid, alias, description)) in types.into_iter().enumerate() {
+            let entry = descr.add(i);
+            (*entry).lexid = lexid;
+            (*entry).alias = pg_sys::pstrdup(alias.as_ptr());
+            (*entry).descr = pg_sys::pstrdup(description.as_ptr());
+        }
+        Internal::from(Some(pg_sys::Datum::from(descr)))
+    }
+}
+
+// The init function of the `analyzed` template, the data of the dictionary is the name of the
+// text analyzer.
+#[pgrx::pg_extern(immutable, parallel_safe)]
+fn text_search_analyzed_init(options: Internal) -> Internal {
+    let mut text_analyzer = None;
+    unsafe {
+        let options = options.unwrap().unwrap().cast_mut_ptr::<pg_sys::List>();
+        for option in PgList::<pg_sys::DefElem>::from_pg(options).iter_ptr() {
+            let name = std::ffi::CStr::from_ptr((*option).defname).to_string_lossy();
+            match name.as_ref() {
+                "text_analyzer" => text_analyzer = Some(pg_sys::defGetString(option)),
+                _ => panic!("Unknown text search dictionary option: {}", name),
+            }
+        }
+    }
+    let Some(text_analyzer) = text_analyzer else {
+        panic!("Missing text search dictionary option: text_analyzer");
+    };
+
+    let name = unsafe { std::ffi::CStr::from_ptr(text_analyzer) };
+    get_text_analyzer(name.to_str().unwrap());
+    Internal::from(Some(pg_sys::Datum::from(unsafe {
+        pg_sys::pstrdup(text_analyzer)
+    })))
+}
+
+// The lexize function of the `analyzed` template. The tokens of a segment are returned as a
+// phrase, the first token of each position but the first one has `TSL_ADDPOS` to move to the
+// next position, and tokens at the same position are variants. Returns null if there is no token,
+// so that the position is not moved for stopwords.
+#[pgrx::pg_extern(immutable, parallel_safe)]
+fn text_search_analyzed_lexize(
+    dict: Internal,
+    token: Internal,
+    len: Internal,
+    _state: Internal,
+) -> Internal {
+    let (text_analyzer, text) = unsafe {
+        let name = std::ffi::CStr::from_ptr(dict.unwrap().unwrap().cast_mut_ptr::<c_char>());
+        let token = token.unwrap().unwrap().cast_mut_ptr::<u8>();
+        let len = len.unwrap().unwrap().value();
+        let bytes = std::slice::from_raw_parts(token, len);
+        (
+            get_text_analyzer(name.to_str().unwrap()),
+            std::str::from_utf8(bytes).expect("Text is not valid UTF-8"),
+        )
+    };
+
+    let groups = position_groups(&text_analyzer, text);
+    let count = groups.iter().map(Vec::len).sum::<usize>();
+    if count == 0 {
+        return Internal::from(Some(pg_sys::Datum::from(std::ptr::null_mut::<
+            pg_sys::TSLexeme,
+        >())));
+    }
+
+    unsafe {
+        // terminated by an entry with null `lexeme`
+        let lexemes = pg_sys::palloc0(std::mem::size_of::<pg_sys::TSLexeme>() * (count + 1))
+            .cast::<pg_sys::TSLexeme>();
+        let mut entry = lexemes;
+        for (i, tokens) in groups.iter().enumerate() {
+            for (variant, token) in tokens.iter().enumerate() {
+                (*entry).nvariant = variant as u16 + 1;
+                if i > 0 && variant == 0 {
+                    (*entry).flags = pg_sys::TSL_ADDPOS as u16;
+                }
+                (*entry).lexeme = pg_sys::pnstrdup(token.as_ptr().cast(), token.len());
+                entry = entry.add(1);
+            }
+        }
+        Internal::from(Some(pg_sys::Datum::from(lexemes)))
+    }
+}
+
+pgrx::extension_sql!(
+    r#"
+CREATE TEXT SEARCH PARSER tokenizer_catalog.analyzed (
+    START = tokenizer_catalog.text_search_parser_start,
+    GETTOKEN = tokenizer_catalog.text_search_parser_gettoken,
+    END = tokenizer_catalog.text_search_parser_end,
+    HEADLINE = pg_catalog.prsd_headline,
+    LEXTYPES = tokenizer_catalog.text_search_parser_lextype
+);
+CREATE TEXT SEARCH TEMPLATE tokenizer_catalog.analyzed (
+    INIT = tokenizer_catalog.text_search_analyzed_init,
+    LEXIZE = tokenizer_catalog.text_search_analyzed_lexize
+);
+"#,
+    name = "analyzed_text_search_parser",
+    requires = [
+        text_search_parser_start,
+        text_search_parser_gettoken,
+        text_search_parser_end,
+        text_search_parser_lextype,
+        text_search_analyzed_init,
+        text_search_analyzed_lexize,
+    ]
+);
+
+// The configuration and its dictionary are created in `tokenizer_catalog` with the same name. They
+// are members of the extension, so that they are dropped with the extension.
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn create_text_search_config(name: &str, text_analyzer: &str) {
+    get_text_analyzer(text_analyzer);
+
+    let qualified_name = format!("tokenizer_catalog.{}", quote_identifier(name));
+    let insert_config = r#"
+        INSERT INTO tokenizer_catalog.text_search_config (name, text_analyzer, qualified_name)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (name) DO NOTHING RETURNING 1
+        "#;
+    let queries = [
+        format!(
+            "CREATE TEXT SEARCH DICTIONARY {} (TEMPLATE = tokenizer_catalog.analyzed, text_analyzer = {})",
+            qualified_name,
+            quote_literal(text_analyzer)
+        ),
+        format!(
+            "CREATE TEXT SEARCH CONFIGURATION {} (PARSER = tokenizer_catalog.analyzed)",
+            qualified_name
+        ),
+        format!(
+            "ALTER TEXT SEARCH CONFIGURATION {} ADD MAPPING FOR text WITH {}",
+            qualified_name, qualified_name
+        ),
+        format!(
+            "ALTER EXTENSION pg_tokenizer ADD TEXT SEARCH DICTIONARY {}",
+            qualified_name
+        ),
+        format!(
+            "ALTER EXTENSION pg_tokenizer ADD TEXT SEARCH CONFIGURATION {}",
+            qualified_name
+        ),
+    ];
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                insert_config,
+                Some(1),
+                &[
+                    name.into(),
+                    text_analyzer.into(),
+                    qualified_name.as_str().into(),
+                ],
+            )
+            .unwrap();
+        if tuptable.is_empty() {
+            panic!("Text search config already exists: {}", name);
+        }
+        for query in &queries {
+            client.update(query, None, &[]).unwrap();
+        }
+    });
+
+    record_dependencies(
+        Catalog::TextSearchConfig,
+        name,
+        &[(Catalog::TextAnalyzer, text_analyzer.to_string())],
+    );
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn drop_text_search_config(name: &str, cascade: default!(bool, false)) {
+    drop_dependents(Catalog::TextSearchConfig, name, cascade);
+
+    let qualified_name = pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                "DELETE FROM tokenizer_catalog.text_search_config WHERE name = $1 RETURNING qualified_name",
+                Some(1),
+                &[name.into()],
+            )
+            .unwrap();
+        if tuptable.is_empty() {
+            return None;
+        }
+        tuptable.first().get_one::<String>().unwrap()
+    });
+    let Some(qualified_name) = qualified_name else {
+        pgrx::warning!("Text search config not found: {}", name);
+        return;
+    };
+
+    let queries = [
+        format!(
+            "ALTER EXTENSION pg_tokenizer DROP TEXT SEARCH CONFIGURATION {}",
+            qualified_name
+        ),
+        format!(
+            "ALTER EXTENSION pg_tokenizer DROP TEXT SEARCH DICTIONARY {}",
+            qualified_name
+        ),
+        format!("DROP TEXT SEARCH CONFIGURATION {}", qualified_name),
+        format!("DROP TEXT SEARCH DICTIONARY {}", qualified_name),
+    ];
+    pgrx::Spi::connect_mut(|client| {
+        for query in &queries {
+            client.update(query, None, &[]).unwrap();
+        }
+    });
+
+    remove_dependencies(Catalog::TextSearchConfig, name);
+}
+
+// PostgreSQL limits the positions in a tsvector, larger positions are clamped like `to_tsvector`
+const MAX_TSVECTOR_POSITION: usize = 16383;
+
+fn quote_lexeme(token: &str) -> String {
+    format!("'{}'", token.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+// Positions start from 1 like `to_tsvector`. Like the configurations of
+// `create_text_search_config`, positions without tokens like stopwords leave no gaps.
+fn analyze_positions(text: &str, text_analyzer_name: &str) -> BTreeMap<usize, Vec<String>> {
+    let text_analyzer = get_text_analyzer(text_analyzer_name);
+    position_groups(&text_analyzer, text)
+        .into_iter()
+        .enumerate()
+        .map(|(i, tokens)| (i + 1, tokens))
+        .collect()
+}
+
+#[pgrx::pg_extern(immutable, parallel_safe)]
+fn _pg_tokenizer_tsvector_text(text: &str, text_analyzer_name: &str) -> String {
+    let mut lexemes = BTreeMap::<String, Vec<usize>>::new();
+    for (position, tokens) in analyze_positions(text, text_analyzer_name) {
+        for token in tokens {
+            lexemes
+                .entry(token)
+                .or_default()
+                .push(position.min(MAX_TSVECTOR_POSITION));
+        }
+    }
+
+    lexemes
+        .into_iter()
+        .map(|(lexeme, positions)| {
+            let positions = positions
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("{}:{}", quote_lexeme(&lexeme), positions)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[pgrx::pg_extern(immutable, parallel_safe)]
+fn _pg_tokenizer_tsquery_text(text: &str, text_analyzer_name: &str, operator: &str) -> String {
+    let positions = analyze_positions(text, text_analyzer_name);
+    let group = |tokens: &[String]| {
+        let tokens = tokens.iter().map(|t| quote_lexeme(t)).collect::<Vec<_>>();
+        match tokens.len() {
+            1 => tokens[0].clone(),
+            _ => format!("({})", tokens.join(" | ")),
+        }
+    };
+
+    match operator {
+        "and" => positions
+            .values()
+            .map(|tokens| group(tokens))
+            .collect::<Vec<_>>()
+            .join(" & "),
+        "or" => {
+            let mut tokens = positions.into_values().flatten().collect::<Vec<_>>();
+            tokens.sort();
+            tokens.dedup();
+            tokens
+                .iter()
+                .map(|t| quote_lexeme(t))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        }
+        "phrase" => positions
+            .values()
+            .map(|tokens| group(tokens))
+            .collect::<Vec<_>>()
+            .join(" <-> "),
+        _ => panic!(
+            "Unknown operator: {}, expected one of and, or, phrase",
+            operator
+        ),
+    }
+}
+
+// tsvector and tsquery are not supported by pgrx, they are built from the text representation
+pgrx::extension_sql!(
+    r#"
+CREATE FUNCTION tokenizer_catalog.analyzer_to_tsvector(text TEXT, text_analyzer_name TEXT) RETURNS tsvector
+IMMUTABLE STRICT PARALLEL SAFE LANGUAGE sql AS $$
+    SELECT tokenizer_catalog._pg_tokenizer_tsvector_text($1, $2)::tsvector
+$$;
+
+CREATE FUNCTION tokenizer_catalog.analyzer_to_tsquery(text TEXT, text_analyzer_name TEXT, operator TEXT DEFAULT 'and') RETURNS tsquery
+IMMUTABLE STRICT PARALLEL SAFE LANGUAGE sql AS $$
+    SELECT tokenizer_catalog._pg_tokenizer_tsquery_text($1, $2, $3)::tsquery
+$$;
+"#,
+    name = "analyzer_tsvector_functions",
+    requires = [_pg_tokenizer_tsvector_text, _pg_tokenizer_tsquery_text]
+);
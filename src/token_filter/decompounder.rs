@@ -0,0 +1,255 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, LazyLock},
+};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    cache::{invalidate_catalog, watch_catalog, Catalog},
+    dependency::drop_dependents,
+    utils::spi_get_one,
+};
+
+use super::TokenFilter;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
+#[serde(deny_unknown_fields)]
+#[validate(schema(function = "DecompounderConfig::validate_subword_size"))]
+pub struct DecompounderConfig {
+    pub word_list: String,
+    #[serde(default = "DecompounderConfig::default_min_word_size")]
+    #[validate(range(min = 1))]
+    pub min_word_size: usize,
+    #[serde(default = "DecompounderConfig::default_min_subword_size")]
+    #[validate(range(min = 1))]
+    pub min_subword_size: usize,
+    #[serde(default = "DecompounderConfig::default_max_subword_size")]
+    #[validate(range(min = 1))]
+    pub max_subword_size: usize,
+    #[serde(default)]
+    pub only_longest_match: bool,
+    #[serde(default = "DecompounderConfig::default_preserve_original")]
+    pub preserve_original: bool,
+    #[serde(default = "DecompounderConfig::default_ignore_case")]
+    pub ignore_case: bool,
+}
+
+impl DecompounderConfig {
+    fn default_min_word_size() -> usize {
+        5
+    }
+    fn default_min_subword_size() -> usize {
+        2
+    }
+    fn default_max_subword_size() -> usize {
+        15
+    }
+    fn default_preserve_original() -> bool {
+        true
+    }
+    fn default_ignore_case() -> bool {
+        true
+    }
+    fn validate_subword_size(&self) -> Result<(), ValidationError> {
+        if self.min_subword_size > self.max_subword_size {
+            return Err(ValidationError::new(
+                "min_subword_size must be less than or equal to max_subword_size",
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub struct WordList {
+    words: HashSet<String>,
+    lowercase_words: HashSet<String>,
+}
+pub type WordListPtr = Arc<WordList>;
+
+impl WordList {
+    // config is a string with multiple lines, each line represents a word
+    pub fn build(config: &str) -> Self {
+        let words = config
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect::<HashSet<_>>();
+        let lowercase_words = words.iter().map(|word| word.to_lowercase()).collect();
+
+        WordList {
+            words,
+            lowercase_words,
+        }
+    }
+}
+
+pub struct DictionaryDecompounder {
+    word_list: WordListPtr,
+    config: DecompounderConfig,
+}
+
+impl DictionaryDecompounder {
+    pub fn new(config: DecompounderConfig) -> Self {
+        if let Err(e) = config.validate() {
+            panic!("Invalid DecompounderConfig: {}", e);
+        }
+
+        DictionaryDecompounder {
+            word_list: get_word_list(&config.word_list),
+            config,
+        }
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        if self.config.ignore_case {
+            self.word_list
+                .lowercase_words
+                .contains(&word.to_lowercase())
+        } else {
+            self.word_list.words.contains(word)
+        }
+    }
+}
+
+impl TokenFilter for DictionaryDecompounder {
+    fn apply(&self, token: String) -> Vec<String> {
+        // byte offsets of all characters, plus the end of the token
+        let boundaries = token
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(token.len()))
+            .collect::<Vec<_>>();
+        let len = boundaries.len() - 1;
+        if len < self.config.min_word_size {
+            return vec![token];
+        }
+
+        let mut subwords = Vec::new();
+        for start in 0..=len.saturating_sub(self.config.min_subword_size) {
+            let mut longest = None;
+            let max_end = (start + self.config.max_subword_size).min(len);
+            for end in (start + self.config.min_subword_size)..=max_end {
+                let subword = &token[boundaries[start]..boundaries[end]];
+                // the whole token is not a subword of itself
+                if end - start == len || !self.contains(subword) {
+                    continue;
+                }
+                if self.config.only_longest_match {
+                    longest = Some(subword);
+                } else {
+                    subwords.push(subword.to_string());
+                }
+            }
+            if let Some(subword) = longest {
+                subwords.push(subword.to_string());
+            }
+        }
+
+        if subwords.is_empty() {
+            return vec![token];
+        }
+        if !self.config.preserve_original {
+            return subwords;
+        }
+
+        let mut results = Vec::with_capacity(subwords.len() + 1);
+        results.push(token);
+        results.extend(subwords);
+        results
+    }
+}
+
+pgrx::extension_sql!(
+    r#"
+CREATE TABLE tokenizer_catalog.word_list (
+    name TEXT NOT NULL UNIQUE PRIMARY KEY,
+    config TEXT NOT NULL
+);
+"#,
+    name = "word_list_table"
+);
+
+type WordListObjectPool = DashMap<String, WordListPtr>;
+static WORD_LIST_OBJECT_POOL: LazyLock<WordListObjectPool> =
+    LazyLock::new(WordListObjectPool::default);
+
+pub fn get_word_list(name: &str) -> WordListPtr {
+    if let Some(model) = WORD_LIST_OBJECT_POOL.get(name) {
+        return model.clone();
+    }
+
+    // the entry lock is not held while loading, since the pool may be cleared by the relcache
+    // callback during the query
+    watch_catalog(Catalog::WordList);
+    let Some(object) = get_word_list_from_database(name) else {
+        panic!("Word list not found: {}", name);
+    };
+    WORD_LIST_OBJECT_POOL.insert(name.to_string(), object.clone());
+    object
+}
+
+pub fn invalidate_word_lists() {
+    WORD_LIST_OBJECT_POOL.clear();
+}
+
+fn get_word_list_from_database(name: &str) -> Option<WordListPtr> {
+    let config: &str = spi_get_one(
+        "SELECT config FROM tokenizer_catalog.word_list WHERE name = $1",
+        &[name.into()],
+    )?;
+
+    let word_list = WordList::build(config);
+    Some(Arc::new(word_list))
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn create_word_list(name: &str, config: &str) {
+    let word_list = WordList::build(config);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                r#"
+                INSERT INTO tokenizer_catalog.word_list (name, config) VALUES ($1, $2)
+                ON CONFLICT (name) DO NOTHING RETURNING 1
+                "#,
+                Some(1),
+                &[name.into(), config.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            panic!("Word list already exists: {}", name);
+        }
+
+        WORD_LIST_OBJECT_POOL.insert(name.to_string(), Arc::new(word_list));
+    });
+
+    invalidate_catalog(Catalog::WordList);
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn drop_word_list(name: &str, cascade: default!(bool, false)) {
+    drop_dependents(Catalog::WordList, name, cascade);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                "DELETE FROM tokenizer_catalog.word_list WHERE name = $1 RETURNING 1",
+                Some(1),
+                &[name.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            pgrx::warning!("Word list not found: {}", name);
+        }
+    });
+
+    WORD_LIST_OBJECT_POOL.remove(name);
+    invalidate_catalog(Catalog::WordList);
+}
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::TokenFilter;
+
+const APOSTROPHES: &[char] = &['\'', '\u{2019}'];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct ElisionConfig {
+    pub articles: Vec<String>,
+    pub ignore_case: bool,
+}
+
+impl Default for ElisionConfig {
+    fn default() -> Self {
+        // same as the default articles of lucene's french analyzer
+        let articles = [
+            "l", "m", "t", "qu", "n", "s", "j", "d", "c", "jusqu", "quoiqu", "lorsqu", "puisqu",
+        ];
+        Self {
+            articles: articles.iter().map(|s| s.to_string()).collect(),
+            ignore_case: true,
+        }
+    }
+}
+
+pub struct Elision {
+    articles: HashSet<String>,
+    ignore_case: bool,
+}
+
+impl Elision {
+    pub fn new(config: ElisionConfig) -> Self {
+        let articles = config
+            .articles
+            .into_iter()
+            .map(|article| {
+                if config.ignore_case {
+                    article.to_lowercase()
+                } else {
+                    article
+                }
+            })
+            .collect();
+
+        Elision {
+            articles,
+            ignore_case: config.ignore_case,
+        }
+    }
+}
+
+impl TokenFilter for Elision {
+    fn apply(&self, token: String) -> Vec<String> {
+        let Some(pos) = token.find(APOSTROPHES) else {
+            return vec![token];
+        };
+
+        let article = &token[..pos];
+        let matched = if self.ignore_case {
+            self.articles.contains(&article.to_lowercase())
+        } else {
+            self.articles.contains(article)
+        };
+        if !matched {
+            return vec![token];
+        }
+
+        let rest = token[pos..].strip_prefix(APOSTROPHES).unwrap();
+        if rest.is_empty() {
+            return vec![token];
+        }
+
+        vec![rest.to_string()]
+    }
+}
+
+pub struct EnglishPossessive;
+
+impl TokenFilter for EnglishPossessive {
+    fn apply(&self, mut token: String) -> Vec<String> {
+        let stem_len = token
+            .strip_suffix(['s', 'S'])
+            .and_then(|s| s.strip_suffix(APOSTROPHES))
+            .map(str::len);
+        if let Some(len) = stem_len.filter(|&len| len > 0) {
+            token.truncate(len);
+        }
+        vec![token]
+    }
+}
@@ -0,0 +1,408 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, LazyLock},
+};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache::{invalidate_catalog, watch_catalog, Catalog},
+    dependency::drop_dependents,
+};
+
+use super::TokenFilter;
+
+type Flag = u32;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FlagMode {
+    Single,
+    Long,
+    Num,
+}
+
+impl FlagMode {
+    fn parse(self, flags: &str) -> Vec<Flag> {
+        let mut parsed: Vec<Flag> = match self {
+            FlagMode::Single => flags.chars().map(|c| c as Flag).collect(),
+            FlagMode::Long => flags
+                .chars()
+                .collect::<Vec<_>>()
+                .chunks(2)
+                .map(|pair| {
+                    pair.iter()
+                        .fold(0, |acc, &c| (acc << 16) | (c as Flag & 0xFFFF))
+                })
+                .collect(),
+            FlagMode::Num => flags
+                .split(',')
+                .map(|flag| {
+                    flag.trim()
+                        .parse()
+                        .unwrap_or_else(|_| panic!("Invalid numeric flag: {}", flag))
+                })
+                .collect(),
+        };
+        parsed.sort_unstable();
+        parsed
+    }
+}
+
+struct AffixRule {
+    flag: Flag,
+    cross_product: bool,
+    strip: String,
+    condition: Option<regex::Regex>,
+}
+
+pub struct HunspellDictionary {
+    // word -> flag sets of all homonyms, each flag set is sorted
+    words: HashMap<String, Vec<Vec<Flag>>>,
+    // affix text -> rules that add it
+    suffixes: HashMap<String, Vec<AffixRule>>,
+    prefixes: HashMap<String, Vec<AffixRule>>,
+    need_affix: Option<Flag>,
+}
+pub type HunspellDictionaryPtr = Arc<HunspellDictionary>;
+
+impl HunspellDictionary {
+    // only the subset of hunspell features needed for stemming is supported:
+    // FLAG, AF, NEEDAFFIX, PFX and SFX
+    pub fn build(aff: &str, dic: &str) -> Self {
+        let mut flag_mode = FlagMode::Single;
+        let mut aliases: Vec<Vec<Flag>> = Vec::new();
+        let mut alias_header_seen = false;
+        let mut need_affix = None;
+        let mut suffixes: HashMap<String, Vec<AffixRule>> = HashMap::new();
+        let mut prefixes: HashMap<String, Vec<AffixRule>> = HashMap::new();
+        // (is_suffix, flag) -> cross_product of the affix header
+        let mut headers: HashMap<(bool, String), bool> = HashMap::new();
+
+        for line in aff.lines() {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            match fields.as_slice() {
+                ["FLAG", "long", ..] => flag_mode = FlagMode::Long,
+                ["FLAG", "num", ..] => flag_mode = FlagMode::Num,
+                // the first AF line is the alias count
+                ["AF", _, ..] if !alias_header_seen => alias_header_seen = true,
+                ["AF", flags, ..] => aliases.push(flag_mode.parse(flags)),
+                ["NEEDAFFIX", flag, ..] => need_affix = flag_mode.parse(flag).first().copied(),
+                [kind @ ("PFX" | "SFX"), flag, cross_product, count]
+                    if count.parse::<usize>().is_ok() && matches!(*cross_product, "Y" | "N") =>
+                {
+                    headers.insert((*kind == "SFX", flag.to_string()), *cross_product == "Y");
+                }
+                [kind @ ("PFX" | "SFX"), flag, strip, add, rest @ ..] => {
+                    let is_suffix = *kind == "SFX";
+                    let Some(&cross_product) = headers.get(&(is_suffix, flag.to_string())) else {
+                        panic!("Affix rule defined before its header: {}", line);
+                    };
+                    let strip = if *strip == "0" { "" } else { strip };
+                    // continuation classes are ignored
+                    let add = add.split_once('/').map_or(*add, |(add, _)| add);
+                    let add = if add == "0" { "" } else { add };
+                    let condition = match rest.first() {
+                        None | Some(&".") => None,
+                        Some(condition) => {
+                            let pattern = if is_suffix {
+                                format!("{}$", condition)
+                            } else {
+                                format!("^{}", condition)
+                            };
+                            Some(regex::Regex::new(&pattern).unwrap_or_else(|e| {
+                                panic!("Invalid affix condition: {}, error: {}", condition, e)
+                            }))
+                        }
+                    };
+                    let rule = AffixRule {
+                        flag: flag_mode.parse(flag)[0],
+                        cross_product,
+                        strip: strip.to_string(),
+                        condition,
+                    };
+                    let affixes = if is_suffix {
+                        &mut suffixes
+                    } else {
+                        &mut prefixes
+                    };
+                    affixes.entry(add.to_string()).or_default().push(rule);
+                }
+                _ => {}
+            }
+        }
+
+        let mut words: HashMap<String, Vec<Vec<Flag>>> = HashMap::new();
+        // the first line is the approximate word count
+        for line in dic.lines().skip_while(|l| l.trim().is_empty()).skip(1) {
+            let Some(entry) = line.split_whitespace().next() else {
+                continue;
+            };
+            let (word, flags) = match entry.split_once('/') {
+                Some((word, flags)) if aliases.is_empty() => (word, flag_mode.parse(flags)),
+                Some((word, alias)) => {
+                    let flags = alias
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|i| i.checked_sub(1))
+                        .and_then(|i| aliases.get(i).cloned())
+                        .unwrap_or_else(|| panic!("Invalid flag alias: {}", alias));
+                    (word, flags)
+                }
+                None => (entry, Vec::new()),
+            };
+            words.entry(word.to_string()).or_default().push(flags);
+        }
+
+        HunspellDictionary {
+            words,
+            suffixes,
+            prefixes,
+            need_affix,
+        }
+    }
+
+    fn lookup(&self, word: &str, required: &[Flag]) -> bool {
+        let Some(homonyms) = self.words.get(word) else {
+            return false;
+        };
+        homonyms.iter().any(|flags| {
+            let needs_affix = self
+                .need_affix
+                .is_some_and(|flag| flags.binary_search(&flag).is_ok());
+            if needs_affix && required.is_empty() {
+                return false;
+            }
+            required
+                .iter()
+                .all(|flag| flags.binary_search(flag).is_ok())
+        })
+    }
+
+    pub fn stem(&self, word: &str) -> Vec<String> {
+        let mut stems = Vec::new();
+        if self.lookup(word, &[]) {
+            stems.push(word.to_string());
+        }
+
+        for i in char_boundaries(word) {
+            let (head, add) = word.split_at(i);
+            if let Some(rules) = self.suffixes.get(add) {
+                for rule in rules {
+                    let candidate = format!("{}{}", head, rule.strip);
+                    if !matches_condition(rule, &candidate) {
+                        continue;
+                    }
+                    if self.lookup(&candidate, &[rule.flag]) {
+                        stems.push(candidate.clone());
+                    }
+                    if rule.cross_product {
+                        self.stem_prefix(&candidate, Some(rule.flag), &mut stems);
+                    }
+                }
+            }
+        }
+        self.stem_prefix(word, None, &mut stems);
+
+        stems
+    }
+
+    fn stem_prefix(&self, word: &str, suffix_flag: Option<Flag>, stems: &mut Vec<String>) {
+        for i in char_boundaries(word) {
+            let (add, tail) = word.split_at(i);
+            let Some(rules) = self.prefixes.get(add) else {
+                continue;
+            };
+            for rule in rules {
+                if suffix_flag.is_some() && !rule.cross_product {
+                    continue;
+                }
+                let candidate = format!("{}{}", rule.strip, tail);
+                if !matches_condition(rule, &candidate) {
+                    continue;
+                }
+                let required = match suffix_flag {
+                    Some(flag) => vec![rule.flag, flag],
+                    None => vec![rule.flag],
+                };
+                if self.lookup(&candidate, &required) {
+                    stems.push(candidate);
+                }
+            }
+        }
+    }
+}
+
+fn char_boundaries(word: &str) -> impl Iterator<Item = usize> + '_ {
+    word.char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(word.len()))
+}
+
+fn matches_condition(rule: &AffixRule, candidate: &str) -> bool {
+    !candidate.is_empty()
+        && rule
+            .condition
+            .as_ref()
+            .is_none_or(|condition| condition.is_match(candidate))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HunspellConfig {
+    pub dictionary: String,
+    #[serde(default)]
+    pub longest_only: bool,
+    #[serde(default = "HunspellConfig::default_dedup")]
+    pub dedup: bool,
+}
+
+impl HunspellConfig {
+    fn default_dedup() -> bool {
+        true
+    }
+}
+
+pub struct HunspellTokenFilter {
+    dictionary: HunspellDictionaryPtr,
+    longest_only: bool,
+    dedup: bool,
+}
+
+impl HunspellTokenFilter {
+    pub fn new(config: HunspellConfig) -> Self {
+        HunspellTokenFilter {
+            dictionary: get_hunspell_dictionary(&config.dictionary),
+            longest_only: config.longest_only,
+            dedup: config.dedup,
+        }
+    }
+}
+
+impl TokenFilter for HunspellTokenFilter {
+    fn apply(&self, token: String) -> Vec<String> {
+        let mut stems = self.dictionary.stem(&token);
+        if stems.is_empty() {
+            return vec![token];
+        }
+
+        if self.longest_only {
+            let longest = stems
+                .into_iter()
+                .rev()
+                .max_by_key(|stem| stem.chars().count())
+                .unwrap();
+            return vec![longest];
+        }
+        if self.dedup {
+            let mut seen = HashSet::new();
+            stems.retain(|stem| seen.insert(stem.clone()));
+        }
+        stems
+    }
+
+    fn skip_keywords(&self) -> bool {
+        true
+    }
+}
+
+pgrx::extension_sql!(
+    r#"
+CREATE TABLE tokenizer_catalog.hunspell_dict (
+    name TEXT NOT NULL UNIQUE PRIMARY KEY,
+    aff TEXT NOT NULL,
+    dic TEXT NOT NULL
+);
+"#,
+    name = "hunspell_dict_table"
+);
+
+type HunspellDictionaryObjectPool = DashMap<String, HunspellDictionaryPtr>;
+static HUNSPELL_DICTIONARY_OBJECT_POOL: LazyLock<HunspellDictionaryObjectPool> =
+    LazyLock::new(HunspellDictionaryObjectPool::default);
+
+pub fn get_hunspell_dictionary(name: &str) -> HunspellDictionaryPtr {
+    if let Some(model) = HUNSPELL_DICTIONARY_OBJECT_POOL.get(name) {
+        return model.clone();
+    }
+
+    // the entry lock is not held while loading, since the pool may be cleared by the relcache
+    // callback during the query
+    watch_catalog(Catalog::HunspellDict);
+    let Some(object) = get_hunspell_dictionary_from_database(name) else {
+        panic!("Hunspell dictionary not found: {}", name);
+    };
+    HUNSPELL_DICTIONARY_OBJECT_POOL.insert(name.to_string(), object.clone());
+    object
+}
+
+pub fn invalidate_hunspell_dictionaries() {
+    HUNSPELL_DICTIONARY_OBJECT_POOL.clear();
+}
+
+fn get_hunspell_dictionary_from_database(name: &str) -> Option<HunspellDictionaryPtr> {
+    pgrx::Spi::connect(|client| {
+        let tuptable = client
+            .select(
+                "SELECT aff, dic FROM tokenizer_catalog.hunspell_dict WHERE name = $1",
+                Some(1),
+                &[name.into()],
+            )
+            .unwrap();
+        if tuptable.is_empty() {
+            return None;
+        }
+        let row = tuptable.first();
+        let aff: &str = row.get(1).unwrap().expect("no aff value");
+        let dic: &str = row.get(2).unwrap().expect("no dic value");
+        Some(Arc::new(HunspellDictionary::build(aff, dic)))
+    })
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn create_hunspell_dict(name: &str, aff: &str, dic: &str) {
+    let dictionary = HunspellDictionary::build(aff, dic);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                r#"
+                INSERT INTO tokenizer_catalog.hunspell_dict (name, aff, dic) VALUES ($1, $2, $3)
+                ON CONFLICT (name) DO NOTHING RETURNING 1
+                "#,
+                Some(1),
+                &[name.into(), aff.into(), dic.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            panic!("Hunspell dictionary already exists: {}", name);
+        }
+
+        HUNSPELL_DICTIONARY_OBJECT_POOL.insert(name.to_string(), Arc::new(dictionary));
+    });
+
+    invalidate_catalog(Catalog::HunspellDict);
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn drop_hunspell_dict(name: &str, cascade: default!(bool, false)) {
+    drop_dependents(Catalog::HunspellDict, name, cascade);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                "DELETE FROM tokenizer_catalog.hunspell_dict WHERE name = $1 RETURNING 1",
+                Some(1),
+                &[name.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            pgrx::warning!("Hunspell dictionary not found: {}", name);
+        }
+    });
+
+    HUNSPELL_DICTIONARY_OBJECT_POOL.remove(name);
+    invalidate_catalog(Catalog::HunspellDict);
+}
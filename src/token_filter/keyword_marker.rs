@@ -0,0 +1,140 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, LazyLock},
+};
+
+use dashmap::DashMap;
+
+use crate::{
+    cache::{invalidate_catalog, watch_catalog, Catalog},
+    dependency::drop_dependents,
+    utils::spi_get_one,
+};
+
+use super::TokenFilter;
+
+pub struct KeywordMarkerTokenFilter {
+    keywords: HashSet<String>,
+}
+pub type KeywordMarkerTokenFilterPtr = Arc<KeywordMarkerTokenFilter>;
+
+impl KeywordMarkerTokenFilter {
+    // config is a string with multiple lines, each line represents a keyword
+    pub fn build(config: &str) -> Self {
+        let keywords = config
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        KeywordMarkerTokenFilter { keywords }
+    }
+}
+
+impl TokenFilter for KeywordMarkerTokenFilter {
+    fn apply(&self, token: String) -> Vec<String> {
+        vec![token]
+    }
+
+    fn apply_batch(&self, tokens: Vec<String>) -> Vec<String> {
+        tokens
+    }
+
+    fn keywords(&self) -> Option<&HashSet<String>> {
+        Some(&self.keywords)
+    }
+}
+
+pgrx::extension_sql!(
+    r#"
+CREATE TABLE tokenizer_catalog.keyword_marker (
+    name TEXT NOT NULL UNIQUE PRIMARY KEY,
+    config TEXT NOT NULL
+);
+"#,
+    name = "keyword_marker_table"
+);
+
+type KeywordMarkerObjectPool = DashMap<String, KeywordMarkerTokenFilterPtr>;
+static KEYWORD_MARKER_OBJECT_POOL: LazyLock<KeywordMarkerObjectPool> =
+    LazyLock::new(KeywordMarkerObjectPool::default);
+
+pub fn get_keyword_marker_token_filter(name: &str) -> KeywordMarkerTokenFilterPtr {
+    if let Some(model) = KEYWORD_MARKER_OBJECT_POOL.get(name) {
+        return model.clone();
+    }
+
+    // the entry lock is not held while loading, since the pool may be cleared by the relcache
+    // callback during the query
+    watch_catalog(Catalog::KeywordMarker);
+    let Some(object) = get_keyword_marker_token_filter_from_database(name) else {
+        panic!("Keyword marker not found: {}", name);
+    };
+    KEYWORD_MARKER_OBJECT_POOL.insert(name.to_string(), object.clone());
+    object
+}
+
+pub fn invalidate_keyword_markers() {
+    KEYWORD_MARKER_OBJECT_POOL.clear();
+}
+
+fn get_keyword_marker_token_filter_from_database(
+    name: &str,
+) -> Option<KeywordMarkerTokenFilterPtr> {
+    let config: &str = spi_get_one(
+        "SELECT config FROM tokenizer_catalog.keyword_marker WHERE name = $1",
+        &[name.into()],
+    )?;
+
+    let keyword_marker = KeywordMarkerTokenFilter::build(config);
+    Some(Arc::new(keyword_marker))
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn create_keyword_marker(name: &str, config: &str) {
+    let keyword_marker = KeywordMarkerTokenFilter::build(config);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                r#"
+                INSERT INTO tokenizer_catalog.keyword_marker (name, config) VALUES ($1, $2)
+                ON CONFLICT (name) DO NOTHING RETURNING 1
+                "#,
+                Some(1),
+                &[name.into(), config.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            panic!("Keyword marker already exists: {}", name);
+        }
+
+        KEYWORD_MARKER_OBJECT_POOL.insert(name.to_string(), Arc::new(keyword_marker));
+    });
+
+    invalidate_catalog(Catalog::KeywordMarker);
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn drop_keyword_marker(name: &str, cascade: default!(bool, false)) {
+    drop_dependents(Catalog::KeywordMarker, name, cascade);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                "DELETE FROM tokenizer_catalog.keyword_marker WHERE name = $1 RETURNING 1",
+                Some(1),
+                &[name.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            pgrx::warning!("Keyword marker not found: {}", name);
+        }
+    });
+
+    KEYWORD_MARKER_OBJECT_POOL.remove(name);
+    invalidate_catalog(Catalog::KeywordMarker);
+}
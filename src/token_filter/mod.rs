@@ -1,17 +1,58 @@
+mod decompounder;
+mod elision;
+mod hunspell;
+mod keyword_marker;
 mod ngram;
 mod pg_dict;
 mod skip_non_alphanumeric;
 mod stemmer;
+mod stemmer_override;
 mod stopwords;
 mod synonym;
+mod word_delimiter;
 
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
+pub use decompounder::invalidate_word_lists;
+pub use hunspell::invalidate_hunspell_dictionaries;
+pub use keyword_marker::invalidate_keyword_markers;
+pub use stemmer_override::invalidate_stemmer_overrides;
+pub use stopwords::invalidate_stopwords;
+pub use synonym::invalidate_synonyms;
+
+use decompounder::{DecompounderConfig, DictionaryDecompounder};
+use elision::{Elision, ElisionConfig, EnglishPossessive};
+use hunspell::{HunspellConfig, HunspellTokenFilter};
 use ngram::{Ngram, NgramConfig};
 use pg_dict::PgDictTokenFilter;
 use serde::{Deserialize, Serialize};
 use skip_non_alphanumeric::SkipNonAlphanumeric;
 use stemmer::{StemmerKind, StemmerTokenFilter};
+use stopwords::StopwordsConfig;
+use synonym::SynonymConfig;
+use word_delimiter::{WordDelimiter, WordDelimiterConfig};
+
+use crate::cache::Catalog;
+
+// Where a token comes from, `position` is the index of the pre-tokenized token, and the offsets
+// are byte offsets in the original text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub position: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    // the span covering both, e.g. for a phrase
+    pub fn merge(self, other: Span) -> Span {
+        Span {
+            position: self.position.min(other.position),
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
 
 pub trait TokenFilter {
     fn apply(&self, token: String) -> Vec<String>;
@@ -22,6 +63,27 @@ pub trait TokenFilter {
             .flat_map(|token| self.apply(token))
             .collect()
     }
+
+    // Like `apply_batch`, the output tokens keep the span of the input tokens they come from.
+    fn apply_batch_with_spans(&self, tokens: Vec<(String, Span)>) -> Vec<(String, Span)> {
+        tokens
+            .into_iter()
+            .flat_map(|(token, span)| {
+                self.apply(token)
+                    .into_iter()
+                    .map(move |token| (token, span))
+            })
+            .collect()
+    }
+
+    // tokens in the returned set are protected from later filters that `skip_keywords`
+    fn keywords(&self) -> Option<&HashSet<String>> {
+        None
+    }
+
+    fn skip_keywords(&self) -> bool {
+        false
+    }
 }
 pub type TokenFilterPtr = Arc<dyn TokenFilter + Sync + Send>;
 
@@ -31,20 +93,65 @@ pub type TokenFilterPtr = Arc<dyn TokenFilter + Sync + Send>;
 pub enum TokenFilterConfig {
     SkipNonAlphanumeric,
     Stemmer(StemmerKind),
-    Stopwords(String),
+    Stopwords(StopwordsConfig),
     PgDict(String),
-    Synonym(String),
+    Synonym(SynonymConfig),
     #[serde(rename = "ngram")]
     NGram(NgramConfig),
+    Elision(ElisionConfig),
+    EnglishPossessive,
+    WordDelimiter(WordDelimiterConfig),
+    KeywordMarker(String),
+    StemmerOverride(String),
+    Hunspell(HunspellConfig),
+    DictionaryDecompounder(DecompounderConfig),
+}
+
+impl TokenFilterConfig {
+    // the catalog object referenced by the token filter
+    pub fn dependency(&self) -> Option<(Catalog, String)> {
+        match self {
+            TokenFilterConfig::Stopwords(config) => {
+                Some((Catalog::Stopwords, config.name().into()))
+            }
+            TokenFilterConfig::Synonym(config) => Some((Catalog::Synonym, config.name().into())),
+            TokenFilterConfig::KeywordMarker(name) => Some((Catalog::KeywordMarker, name.clone())),
+            TokenFilterConfig::StemmerOverride(name) => {
+                Some((Catalog::StemmerOverride, name.clone()))
+            }
+            TokenFilterConfig::Hunspell(config) => {
+                Some((Catalog::HunspellDict, config.dictionary.clone()))
+            }
+            TokenFilterConfig::DictionaryDecompounder(config) => {
+                Some((Catalog::WordList, config.word_list.clone()))
+            }
+            _ => None,
+        }
+    }
 }
 
 pub fn get_token_filter(config: TokenFilterConfig) -> TokenFilterPtr {
     match config {
         TokenFilterConfig::SkipNonAlphanumeric => Arc::new(SkipNonAlphanumeric),
         TokenFilterConfig::Stemmer(kind) => Arc::new(StemmerTokenFilter::new(kind)),
-        TokenFilterConfig::Stopwords(name) => stopwords::get_stopwords_token_filter(&name),
+        TokenFilterConfig::Stopwords(config) => {
+            stopwords::get_stopwords_token_filter_with_config(config)
+        }
         TokenFilterConfig::PgDict(name) => Arc::new(PgDictTokenFilter::new(&name)),
-        TokenFilterConfig::Synonym(name) => synonym::get_synonym_token_filter(&name),
+        TokenFilterConfig::Synonym(config) => synonym::get_synonym_token_filter_with_config(config),
         TokenFilterConfig::NGram(config) => Arc::new(Ngram::new(config)),
+        TokenFilterConfig::Elision(config) => Arc::new(Elision::new(config)),
+        TokenFilterConfig::EnglishPossessive => Arc::new(EnglishPossessive),
+        TokenFilterConfig::WordDelimiter(config) => Arc::new(WordDelimiter::new(config)),
+        TokenFilterConfig::KeywordMarker(name) => {
+            keyword_marker::get_keyword_marker_token_filter(&name)
+        }
+        TokenFilterConfig::StemmerOverride(name) => {
+            stemmer_override::get_stemmer_override_token_filter(&name)
+        }
+        TokenFilterConfig::Hunspell(config) => Arc::new(HunspellTokenFilter::new(config)),
+        TokenFilterConfig::DictionaryDecompounder(config) => {
+            Arc::new(DictionaryDecompounder::new(config))
+        }
     }
 }
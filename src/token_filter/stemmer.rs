@@ -105,4 +105,8 @@ impl TokenFilter for StemmerTokenFilter {
         }
         tokens
     }
+
+    fn skip_keywords(&self) -> bool {
+        true
+    }
 }
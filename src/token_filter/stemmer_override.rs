@@ -0,0 +1,162 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, LazyLock},
+};
+
+use dashmap::DashMap;
+
+use crate::{
+    cache::{invalidate_catalog, watch_catalog, Catalog},
+    dependency::drop_dependents,
+    utils::spi_get_one,
+};
+
+use super::TokenFilter;
+
+pub struct StemmerOverrideTokenFilter {
+    overrides: HashMap<String, String>,
+    stems: HashSet<String>,
+}
+pub type StemmerOverrideTokenFilterPtr = Arc<StemmerOverrideTokenFilter>;
+
+impl StemmerOverrideTokenFilter {
+    // config is a string with multiple lines, each line represents a rule like `running, runs => run`
+    pub fn build(config: &str) -> Self {
+        let mut overrides = HashMap::new();
+        let mut stems = HashSet::new();
+
+        for line in config.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((terms, stem)) = line.split_once("=>") else {
+                panic!("Invalid stemmer override rule: {}", line);
+            };
+            let stem = stem.trim();
+            if stem.is_empty() {
+                panic!("Invalid stemmer override rule: {}", line);
+            }
+
+            for term in terms.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                if overrides
+                    .insert(term.to_string(), stem.to_string())
+                    .is_some()
+                {
+                    panic!("Duplicate term defined: {}", term);
+                }
+            }
+            stems.insert(stem.to_string());
+        }
+
+        StemmerOverrideTokenFilter { overrides, stems }
+    }
+}
+
+impl TokenFilter for StemmerOverrideTokenFilter {
+    fn apply(&self, token: String) -> Vec<String> {
+        match self.overrides.get(&token) {
+            Some(stem) => vec![stem.clone()],
+            None => vec![token],
+        }
+    }
+
+    // overridden stems should not be stemmed again
+    fn keywords(&self) -> Option<&HashSet<String>> {
+        Some(&self.stems)
+    }
+}
+
+pgrx::extension_sql!(
+    r#"
+CREATE TABLE tokenizer_catalog.stemmer_override (
+    name TEXT NOT NULL UNIQUE PRIMARY KEY,
+    config TEXT NOT NULL
+);
+"#,
+    name = "stemmer_override_table"
+);
+
+type StemmerOverrideObjectPool = DashMap<String, StemmerOverrideTokenFilterPtr>;
+static STEMMER_OVERRIDE_OBJECT_POOL: LazyLock<StemmerOverrideObjectPool> =
+    LazyLock::new(StemmerOverrideObjectPool::default);
+
+pub fn get_stemmer_override_token_filter(name: &str) -> StemmerOverrideTokenFilterPtr {
+    if let Some(model) = STEMMER_OVERRIDE_OBJECT_POOL.get(name) {
+        return model.clone();
+    }
+
+    // the entry lock is not held while loading, since the pool may be cleared by the relcache
+    // callback during the query
+    watch_catalog(Catalog::StemmerOverride);
+    let Some(object) = get_stemmer_override_token_filter_from_database(name) else {
+        panic!("Stemmer override not found: {}", name);
+    };
+    STEMMER_OVERRIDE_OBJECT_POOL.insert(name.to_string(), object.clone());
+    object
+}
+
+pub fn invalidate_stemmer_overrides() {
+    STEMMER_OVERRIDE_OBJECT_POOL.clear();
+}
+
+fn get_stemmer_override_token_filter_from_database(
+    name: &str,
+) -> Option<StemmerOverrideTokenFilterPtr> {
+    let config: &str = spi_get_one(
+        "SELECT config FROM tokenizer_catalog.stemmer_override WHERE name = $1",
+        &[name.into()],
+    )?;
+
+    let stemmer_override = StemmerOverrideTokenFilter::build(config);
+    Some(Arc::new(stemmer_override))
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn create_stemmer_override(name: &str, config: &str) {
+    let stemmer_override = StemmerOverrideTokenFilter::build(config);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                r#"
+                INSERT INTO tokenizer_catalog.stemmer_override (name, config) VALUES ($1, $2)
+                ON CONFLICT (name) DO NOTHING RETURNING 1
+                "#,
+                Some(1),
+                &[name.into(), config.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            panic!("Stemmer override already exists: {}", name);
+        }
+
+        STEMMER_OVERRIDE_OBJECT_POOL.insert(name.to_string(), Arc::new(stemmer_override));
+    });
+
+    invalidate_catalog(Catalog::StemmerOverride);
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn drop_stemmer_override(name: &str, cascade: default!(bool, false)) {
+    drop_dependents(Catalog::StemmerOverride, name, cascade);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                "DELETE FROM tokenizer_catalog.stemmer_override WHERE name = $1 RETURNING 1",
+                Some(1),
+                &[name.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            pgrx::warning!("Stemmer override not found: {}", name);
+        }
+    });
+
+    STEMMER_OVERRIDE_OBJECT_POOL.remove(name);
+    invalidate_catalog(Catalog::StemmerOverride);
+}
@@ -3,12 +3,50 @@ use std::{
     sync::{Arc, LazyLock},
 };
 
-use dashmap::{DashMap, Entry};
+use dashmap::DashMap;
+use pgrx::{
+    iter::TableIterator, name, pg_sys::panic::ErrorReportable, prelude::PgHeapTuple,
+    AllocatedByPostgres,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::utils::spi_get_one;
+use crate::{
+    cache::{invalidate_catalog, watch_catalog, Catalog},
+    character_filter::UnicodeNormalizationConfig,
+    dependency::drop_dependents,
+    model::warn_stale_custom_models,
+    text_analyzer::invalidate_text_analyzers,
+    utils::{read_config_or_source, spi_get_one, TableSource},
+};
+
+use super::{TokenFilter, TokenFilterPtr};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StopwordsConfig {
+    Name(String),
+    Options(StopwordsOptions),
+}
+
+impl StopwordsConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            StopwordsConfig::Name(name) => name,
+            StopwordsConfig::Options(options) => &options.name,
+        }
+    }
+}
 
-use super::TokenFilter;
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StopwordsOptions {
+    pub name: String,
+    #[serde(default)]
+    pub ignore_case: bool,
+    // normalize both stopwords and tokens before matching
+    #[serde(default)]
+    pub unicode_normalization: Option<UnicodeNormalizationConfig>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -19,10 +57,15 @@ pub type StopwordsTokenFilterPtr = Arc<StopwordsTokenFilter>;
 
 impl StopwordsTokenFilter {
     // config is a string with multiple lines, each line represents a stopword
+    // lines are trimmed, empty lines and lines starting with `#` are ignored
     pub fn build(config: &str) -> Self {
         let mut stopwords = HashSet::new();
 
         for line in config.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
             stopwords.insert(line.to_string());
         }
 
@@ -40,11 +83,69 @@ impl TokenFilter for StopwordsTokenFilter {
     }
 }
 
+struct NormalizedStopwordsTokenFilter {
+    stopwords: HashSet<String>,
+    ignore_case: bool,
+    unicode_normalization: Option<UnicodeNormalizationConfig>,
+}
+
+impl NormalizedStopwordsTokenFilter {
+    fn new(stopwords: &StopwordsTokenFilter, options: StopwordsOptions) -> Self {
+        let mut filter = NormalizedStopwordsTokenFilter {
+            stopwords: HashSet::new(),
+            ignore_case: options.ignore_case,
+            unicode_normalization: options.unicode_normalization,
+        };
+        filter.stopwords = stopwords
+            .stopwords
+            .iter()
+            .map(|word| filter.normalize(word))
+            .collect();
+        filter
+    }
+
+    fn normalize(&self, word: &str) -> String {
+        let word = match &self.unicode_normalization {
+            Some(form) => form.normalize(word),
+            None => word.to_string(),
+        };
+        if self.ignore_case {
+            word.to_lowercase()
+        } else {
+            word
+        }
+    }
+}
+
+impl TokenFilter for NormalizedStopwordsTokenFilter {
+    fn apply(&self, token: String) -> Vec<String> {
+        if self.stopwords.contains(&self.normalize(&token)) {
+            vec![]
+        } else {
+            vec![token]
+        }
+    }
+}
+
+pub fn get_stopwords_token_filter_with_config(config: StopwordsConfig) -> TokenFilterPtr {
+    match config {
+        StopwordsConfig::Name(name) => get_stopwords_token_filter(&name),
+        StopwordsConfig::Options(options) => {
+            if !options.ignore_case && options.unicode_normalization.is_none() {
+                return get_stopwords_token_filter(&options.name);
+            }
+            let stopwords = get_stopwords_token_filter(&options.name);
+            Arc::new(NormalizedStopwordsTokenFilter::new(&stopwords, options))
+        }
+    }
+}
+
 pgrx::extension_sql!(
     r#"
 CREATE TABLE tokenizer_catalog.stopwords (
     name TEXT NOT NULL UNIQUE PRIMARY KEY,
-    config TEXT NOT NULL
+    config TEXT NOT NULL,
+    source TEXT
 );
 "#,
     name = "stopwords_table"
@@ -59,42 +160,75 @@ pub fn get_stopwords_token_filter(name: &str) -> StopwordsTokenFilterPtr {
         return model.clone();
     }
 
-    match STOPWORDS_OBJECT_POOL.entry(name.to_string()) {
-        Entry::Occupied(entry) => entry.get().clone(),
-        Entry::Vacant(entry) => {
-            if let Some(object) = get_stopwords_token_filter_from_database(name) {
-                entry.insert(object.clone());
-                return object;
-            }
+    // the entry lock is not held while loading, since the pool may be cleared by the relcache
+    // callback during the query
+    watch_catalog(Catalog::Stopwords);
+    let Some(object) = get_stopwords_token_filter_from_database(name) else {
+        panic!("Stopwords not found: {}", name);
+    };
+    STOPWORDS_OBJECT_POOL.insert(name.to_string(), object.clone());
+    object
+}
 
-            panic!("Stopwords not found: {}", name);
-        }
-    }
+pub fn invalidate_stopwords() {
+    STOPWORDS_OBJECT_POOL.clear();
 }
 
 fn get_stopwords_token_filter_from_database(name: &str) -> Option<StopwordsTokenFilterPtr> {
-    let config: &str = spi_get_one(
-        "SELECT config FROM tokenizer_catalog.stopwords WHERE name = $1",
-        &[name.into()],
-    )?;
+    let (config, source) = pgrx::Spi::connect(|client| {
+        client
+            .select(
+                "SELECT config, source FROM tokenizer_catalog.stopwords WHERE name = $1",
+                Some(1),
+                &[name.into()],
+            )
+            .unwrap_or_report()
+            .first()
+            .get_two::<String, String>()
+            .ok()
+    })?;
 
-    let stopwords = StopwordsTokenFilter::build(config);
+    let config = match source {
+        Some(source) => TableSource::parse(&source).read(),
+        None => config.expect("config is missing"),
+    };
+    let stopwords = StopwordsTokenFilter::build(&config);
     Some(Arc::new(stopwords))
 }
 
+fn get_stopwords_source(name: &str) -> Option<String> {
+    spi_get_one(
+        "SELECT source FROM tokenizer_catalog.stopwords WHERE name = $1 AND source IS NOT NULL",
+        &[name.into()],
+    )
+}
+
+fn stopwords_trigger_name(name: &str) -> String {
+    format!("stopwords_{}_trigger", name)
+}
+
+// either `config` or `source` should be provided, `source` is a text column of a user table
+// like `public.my_stopwords(word)`, whose each row is a stopword
 #[pgrx::pg_extern(volatile, parallel_safe)]
-fn create_stopwords(name: &str, config: &str) {
-    let stopwords = StopwordsTokenFilter::build(config);
+fn create_stopwords(
+    name: &str,
+    config: default!(Option<&str>, "NULL"),
+    source: default!(Option<&str>, "NULL"),
+) {
+    let (config, table_source) = read_config_or_source(config, source);
+    let stopwords = StopwordsTokenFilter::build(&config);
+    // the content is read from the source table every time, so it's not stored
+    let stored_config = if source.is_some() { "" } else { &config };
 
     pgrx::Spi::connect_mut(|client| {
         let tuptable = client
             .update(
                 r#"
-                INSERT INTO tokenizer_catalog.stopwords (name, config) VALUES ($1, $2)
+                INSERT INTO tokenizer_catalog.stopwords (name, config, source) VALUES ($1, $2, $3)
                 ON CONFLICT (name) DO NOTHING RETURNING 1
                 "#,
                 Some(1),
-                &[name.into(), config.into()],
+                &[name.into(), stored_config.into(), source.into()],
             )
             .unwrap();
 
@@ -102,6 +236,14 @@ fn create_stopwords(name: &str, config: &str) {
             panic!("Stopwords already exists: {}", name);
         }
 
+        if let Some(table_source) = &table_source {
+            table_source.create_trigger(
+                &stopwords_trigger_name(name),
+                "stopwords_source_trigger",
+                name,
+            );
+        }
+
         if STOPWORDS_OBJECT_POOL
             .insert(name.to_string(), Arc::new(stopwords))
             .is_some()
@@ -109,10 +251,67 @@ fn create_stopwords(name: &str, config: &str) {
             panic!("Stopwords already exists: {}", name);
         }
     });
+
+    invalidate_catalog(Catalog::Stopwords);
 }
 
 #[pgrx::pg_extern(volatile, parallel_safe)]
-fn drop_stopwords(name: &str) {
+fn alter_stopwords(
+    name: &str,
+    config: default!(Option<&str>, "NULL"),
+    source: default!(Option<&str>, "NULL"),
+) {
+    let (config, table_source) = read_config_or_source(config, source);
+    let stopwords = StopwordsTokenFilter::build(&config);
+    let stored_config = if source.is_some() { "" } else { &config };
+    let old_source = get_stopwords_source(name);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                r#"
+                UPDATE tokenizer_catalog.stopwords SET config = $2, source = $3 WHERE name = $1
+                RETURNING 1
+                "#,
+                Some(1),
+                &[name.into(), stored_config.into(), source.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            panic!("Stopwords not found: {}", name);
+        }
+    });
+
+    if let Some(table_source) = old_source.and_then(|s| TableSource::try_parse(&s)) {
+        table_source.drop_trigger(&stopwords_trigger_name(name));
+    }
+    if let Some(table_source) = &table_source {
+        table_source.create_trigger(
+            &stopwords_trigger_name(name),
+            "stopwords_source_trigger",
+            name,
+        );
+    }
+
+    STOPWORDS_OBJECT_POOL.insert(name.to_string(), Arc::new(stopwords));
+    // text analyzers hold the stopwords, so they are rebuilt too
+    invalidate_text_analyzers();
+    warn_stale_custom_models(Catalog::Stopwords, name);
+
+    invalidate_catalog(Catalog::Stopwords);
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn drop_stopwords(name: &str, cascade: default!(bool, false)) {
+    drop_dependents(Catalog::Stopwords, name, cascade);
+
+    // the trigger is dropped with the table if the table does not exist
+    if let Some(table_source) = get_stopwords_source(name).and_then(|s| TableSource::try_parse(&s))
+    {
+        table_source.drop_trigger(&stopwords_trigger_name(name));
+    }
+
     pgrx::Spi::connect_mut(|client| {
         let tuptable = client
             .update(
@@ -128,6 +327,24 @@ fn drop_stopwords(name: &str) {
     });
 
     STOPWORDS_OBJECT_POOL.remove(name);
+    invalidate_catalog(Catalog::Stopwords);
+}
+
+#[pgrx::pg_trigger]
+fn stopwords_source_trigger<'a>(
+    trigger: &'a pgrx::PgTrigger<'a>,
+) -> Result<Option<PgHeapTuple<'a, AllocatedByPostgres>>, ()> {
+    let tg_argv = trigger.extra_args().expect("trigger arguments are missing");
+    let [name] = tg_argv.as_slice() else {
+        panic!("Invalid trigger arguments");
+    };
+
+    // text analyzers hold the stopwords, so they are rebuilt too
+    STOPWORDS_OBJECT_POOL.remove(name);
+    invalidate_text_analyzers();
+
+    invalidate_catalog(Catalog::Stopwords);
+    Ok(None)
 }
 
 macro_rules! STOPWORDS_DIR {
@@ -136,9 +353,50 @@ macro_rules! STOPWORDS_DIR {
     };
 }
 
-static LUCENE_ENGLISH_STOPWORDS: &str = include_str!(concat!(STOPWORDS_DIR!(), "/lucene_english"));
-static NLTK_ENGLISH_STOPWORDS: &str = include_str!(concat!(STOPWORDS_DIR!(), "/nltk_english"));
-static ISO_ENGLISH_STOPWORDS: &str = include_str!(concat!(STOPWORDS_DIR!(), "/iso_english"));
+macro_rules! builtin_stopwords {
+    ($($name:literal),* $(,)?) => {
+        &[$(($name, include_str!(concat!(STOPWORDS_DIR!(), "/", $name)))),*]
+    };
+}
+
+// (name, content) of the stopwords lists shipped with the extension
+static BUILTIN_STOPWORDS: &[(&str, &str)] = builtin_stopwords![
+    "lucene_english",
+    "nltk_english",
+    "iso_english",
+    "snowball_arabic",
+    "snowball_armenian",
+    "snowball_basque",
+    "snowball_catalan",
+    "snowball_danish",
+    "snowball_dutch",
+    "snowball_english",
+    "snowball_estonian",
+    "snowball_finnish",
+    "snowball_french",
+    "snowball_german",
+    "snowball_greek",
+    "snowball_hindi",
+    "snowball_hungarian",
+    "snowball_indonesian",
+    "snowball_irish",
+    "snowball_italian",
+    "snowball_lithuanian",
+    "snowball_nepali",
+    "snowball_norwegian",
+    "snowball_portuguese",
+    "snowball_romanian",
+    "snowball_russian",
+    "snowball_serbian",
+    "snowball_spanish",
+    "snowball_swedish",
+    "snowball_tamil",
+    "snowball_turkish",
+    "snowball_yiddish",
+    "chinese",
+    "japanese",
+    "korean",
+];
 
 fn create_stopwords_when_init(name: &str, config: &str) {
     pgrx::Spi::connect_mut(|client| {
@@ -157,9 +415,17 @@ fn create_stopwords_when_init(name: &str, config: &str) {
 
 #[pgrx::pg_extern]
 pub fn _pg_tokenizer_stopwords_init() {
-    create_stopwords_when_init("lucene_english", LUCENE_ENGLISH_STOPWORDS);
-    create_stopwords_when_init("nltk_english", NLTK_ENGLISH_STOPWORDS);
-    create_stopwords_when_init("iso_english", ISO_ENGLISH_STOPWORDS);
+    for (name, content) in BUILTIN_STOPWORDS {
+        create_stopwords_when_init(name, content);
+    }
+}
+
+#[pgrx::pg_extern(immutable, parallel_safe)]
+fn list_builtin_stopwords() -> TableIterator<'static, (name!(name, String), name!(size, i32))> {
+    TableIterator::new(BUILTIN_STOPWORDS.iter().map(|(name, content)| {
+        let size = StopwordsTokenFilter::build(content).stopwords.len();
+        (name.to_string(), size as i32)
+    }))
 }
 
 pgrx::extension_sql!(
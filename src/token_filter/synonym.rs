@@ -1,54 +1,242 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
+    ops::Range,
     sync::{Arc, LazyLock},
 };
 
-use dashmap::{DashMap, Entry};
+use dashmap::DashMap;
+use pgrx::{pg_sys::panic::ErrorReportable, prelude::PgHeapTuple, AllocatedByPostgres};
 use serde::{Deserialize, Serialize};
 
-use crate::utils::spi_get_one;
+use crate::{
+    cache::{invalidate_catalog, watch_catalog, Catalog},
+    dependency::drop_dependents,
+    model::warn_stale_custom_models,
+    text_analyzer::invalidate_text_analyzers,
+    utils::{read_config_or_source, spi_get_one, TableSource},
+};
+
+use super::{Span, TokenFilter, TokenFilterPtr};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SynonymConfig {
+    Name(String),
+    Options(SynonymOptions),
+}
 
-use super::TokenFilter;
+impl SynonymConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            SynonymConfig::Name(name) => name,
+            SynonymConfig::Options(options) => &options.name,
+        }
+    }
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
+pub struct SynonymOptions {
+    pub name: String,
+    #[serde(default)]
+    pub mode: SynonymMode,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SynonymMode {
+    // replace the matched tokens with the first synonym of the group
+    #[default]
+    Replace,
+    // replace the matched tokens with all synonyms of the group
+    Expand,
+}
+
+#[derive(Debug, Default)]
+struct SynonymRule {
+    replace: Vec<Vec<String>>,
+    expand: Vec<Vec<String>>,
+}
+
+impl SynonymRule {
+    fn outputs(&self, mode: SynonymMode) -> &[Vec<String>] {
+        match mode {
+            SynonymMode::Replace => &self.replace,
+            SynonymMode::Expand => &self.expand,
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct SynonymTokenFilter {
-    synonyms: HashMap<String, String>,
+    // token sequence -> its synonyms
+    rules: HashMap<Vec<String>, SynonymRule>,
+    max_len: usize,
 }
 pub type SynonymTokenFilterPtr = Arc<SynonymTokenFilter>;
 
 impl SynonymTokenFilter {
-    // config is a string with multiple lines, each line represents a group of synonyms for a single word, separated by spaces
+    // config is a string with multiple lines, each line is one of:
+    // - `pgsql postgres postgresql`: single-word synonyms separated by spaces
+    // - `new york, nyc, big apple`: synonyms separated by commas, each one can contain multiple words
+    // - `nyc, big apple => new york`: one-way rule, the left side is replaced by the right side
+    // empty lines and lines starting with `#` are ignored
     pub fn build(config: &str) -> Self {
-        let mut synonyms = HashMap::new();
-        let mut duplicate_check = HashSet::new();
+        let mut rules: HashMap<Vec<String>, SynonymRule> = HashMap::new();
 
         for line in config.lines() {
-            let mut words = line.split_whitespace();
-            if let Some(first) = words.next() {
-                if !duplicate_check.insert(first.to_string()) {
-                    panic!("Duplicate word defined: {}", first);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some((left, right)) = line.split_once("=>") {
+                let inputs = parse_phrases(left);
+                let outputs = parse_phrases(right);
+                if inputs.is_empty() || outputs.is_empty() {
+                    panic!("Invalid synonym rule: {}", line);
+                }
+                for input in inputs {
+                    let rule = rules.entry(input).or_default();
+                    merge(&mut rule.replace, &outputs);
+                    merge(&mut rule.expand, &outputs);
                 }
-                for word in words {
-                    if !duplicate_check.insert(word.to_string()) {
-                        panic!("Duplicate word defined: {}", word);
+                continue;
+            }
+
+            let group = if line.contains(',') {
+                parse_phrases(line)
+            } else {
+                line.split_whitespace()
+                    .map(|word| vec![word.to_string()])
+                    .collect()
+            };
+            let Some(first) = group.first() else {
+                continue;
+            };
+            for input in &group {
+                let rule = rules.entry(input.clone()).or_default();
+                merge(&mut rule.replace, std::slice::from_ref(first));
+                merge(&mut rule.expand, &group);
+            }
+        }
+
+        let max_len = rules.keys().map(Vec::len).max().unwrap_or(0);
+        SynonymTokenFilter { rules, max_len }
+    }
+
+    // calls `emit` with the range of the input tokens and one of their outputs
+    fn rewrite<'a>(
+        &'a self,
+        tokens: &'a [String],
+        mode: SynonymMode,
+        mut emit: impl FnMut(Range<usize>, &'a [String]),
+    ) {
+        let mut i = 0;
+        'outer: while i < tokens.len() {
+            // prefer the longest match
+            for len in (1..=self.max_len.min(tokens.len() - i)).rev() {
+                if let Some(rule) = self.rules.get(&tokens[i..i + len]) {
+                    for output in rule.outputs(mode) {
+                        emit(i..i + len, output);
                     }
-                    synonyms.insert(word.to_string(), first.to_string());
+                    i += len;
+                    continue 'outer;
                 }
             }
+            emit(i..i + 1, std::slice::from_ref(&tokens[i]));
+            i += 1;
         }
+    }
+
+    pub fn apply_with_mode(&self, tokens: Vec<String>, mode: SynonymMode) -> Vec<String> {
+        let mut results = Vec::with_capacity(tokens.len());
+        self.rewrite(&tokens, mode, |_, output| {
+            results.extend(output.iter().cloned())
+        });
+        results
+    }
 
-        SynonymTokenFilter { synonyms }
+    // the outputs of a phrase get the span covering the phrase
+    pub fn apply_with_mode_and_spans(
+        &self,
+        tokens: Vec<(String, Span)>,
+        mode: SynonymMode,
+    ) -> Vec<(String, Span)> {
+        let (tokens, spans): (Vec<_>, Vec<_>) = tokens.into_iter().unzip();
+        let mut results = Vec::with_capacity(tokens.len());
+        self.rewrite(&tokens, mode, |range, output| {
+            let span = spans[range.start].merge(spans[range.end - 1]);
+            results.extend(output.iter().map(|token| (token.clone(), span)));
+        });
+        results
+    }
+}
+
+fn parse_phrases(s: &str) -> Vec<Vec<String>> {
+    s.split(',')
+        .map(|phrase| {
+            phrase
+                .split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .filter(|phrase| !phrase.is_empty())
+        .collect()
+}
+
+// duplicated definitions are merged instead of overwritten
+fn merge(target: &mut Vec<Vec<String>>, phrases: &[Vec<String>]) {
+    for phrase in phrases {
+        if !target.contains(phrase) {
+            target.push(phrase.clone());
+        }
     }
 }
 
 impl TokenFilter for SynonymTokenFilter {
     fn apply(&self, token: String) -> Vec<String> {
-        if let Some(synonym) = self.synonyms.get(&token) {
-            vec![synonym.clone()]
-        } else {
-            vec![token]
-        }
+        self.apply_with_mode(vec![token], SynonymMode::Replace)
+    }
+
+    fn apply_batch(&self, tokens: Vec<String>) -> Vec<String> {
+        self.apply_with_mode(tokens, SynonymMode::Replace)
+    }
+
+    fn apply_batch_with_spans(&self, tokens: Vec<(String, Span)>) -> Vec<(String, Span)> {
+        self.apply_with_mode_and_spans(tokens, SynonymMode::Replace)
+    }
+}
+
+struct ExpandSynonymTokenFilter {
+    synonym: SynonymTokenFilterPtr,
+}
+
+impl TokenFilter for ExpandSynonymTokenFilter {
+    fn apply(&self, token: String) -> Vec<String> {
+        self.synonym
+            .apply_with_mode(vec![token], SynonymMode::Expand)
+    }
+
+    fn apply_batch(&self, tokens: Vec<String>) -> Vec<String> {
+        self.synonym.apply_with_mode(tokens, SynonymMode::Expand)
+    }
+
+    fn apply_batch_with_spans(&self, tokens: Vec<(String, Span)>) -> Vec<(String, Span)> {
+        self.synonym
+            .apply_with_mode_and_spans(tokens, SynonymMode::Expand)
+    }
+}
+
+pub fn get_synonym_token_filter_with_config(config: SynonymConfig) -> TokenFilterPtr {
+    match config {
+        SynonymConfig::Name(name) => get_synonym_token_filter(&name),
+        SynonymConfig::Options(SynonymOptions { name, mode }) => match mode {
+            SynonymMode::Replace => get_synonym_token_filter(&name),
+            SynonymMode::Expand => Arc::new(ExpandSynonymTokenFilter {
+                synonym: get_synonym_token_filter(&name),
+            }),
+        },
     }
 }
 
@@ -56,7 +244,8 @@ pgrx::extension_sql!(
     r#"
 CREATE TABLE tokenizer_catalog.synonym (
     name TEXT NOT NULL UNIQUE PRIMARY KEY,
-    config TEXT NOT NULL
+    config TEXT NOT NULL,
+    source TEXT
 );
 "#,
     name = "synonym_table"
@@ -70,42 +259,75 @@ pub fn get_synonym_token_filter(name: &str) -> SynonymTokenFilterPtr {
         return model.clone();
     }
 
-    match SYNONYM_OBJECT_POOL.entry(name.to_string()) {
-        Entry::Occupied(entry) => entry.get().clone(),
-        Entry::Vacant(entry) => {
-            if let Some(object) = get_synonym_token_filter_from_database(name) {
-                entry.insert(object.clone());
-                return object;
-            }
+    // the entry lock is not held while loading, since the pool may be cleared by the relcache
+    // callback during the query
+    watch_catalog(Catalog::Synonym);
+    let Some(object) = get_synonym_token_filter_from_database(name) else {
+        panic!("Synonym not found: {}", name);
+    };
+    SYNONYM_OBJECT_POOL.insert(name.to_string(), object.clone());
+    object
+}
 
-            panic!("Synonym not found: {}", name);
-        }
-    }
+pub fn invalidate_synonyms() {
+    SYNONYM_OBJECT_POOL.clear();
 }
 
 fn get_synonym_token_filter_from_database(name: &str) -> Option<SynonymTokenFilterPtr> {
-    let config: &str = spi_get_one(
-        "SELECT config FROM tokenizer_catalog.synonym WHERE name = $1",
-        &[name.into()],
-    )?;
+    let (config, source) = pgrx::Spi::connect(|client| {
+        client
+            .select(
+                "SELECT config, source FROM tokenizer_catalog.synonym WHERE name = $1",
+                Some(1),
+                &[name.into()],
+            )
+            .unwrap_or_report()
+            .first()
+            .get_two::<String, String>()
+            .ok()
+    })?;
 
-    let synonym = SynonymTokenFilter::build(config);
+    let config = match source {
+        Some(source) => TableSource::parse(&source).read(),
+        None => config.expect("config is missing"),
+    };
+    let synonym = SynonymTokenFilter::build(&config);
     Some(Arc::new(synonym))
 }
 
+fn get_synonym_source(name: &str) -> Option<String> {
+    spi_get_one(
+        "SELECT source FROM tokenizer_catalog.synonym WHERE name = $1 AND source IS NOT NULL",
+        &[name.into()],
+    )
+}
+
+fn synonym_trigger_name(name: &str) -> String {
+    format!("synonym_{}_trigger", name)
+}
+
+// either `config` or `source` should be provided, `source` is a text column of a user table
+// like `public.my_synonyms(rule)`, whose each row is a line of the config
 #[pgrx::pg_extern(volatile, parallel_safe)]
-fn create_synonym(name: &str, config: &str) {
-    let synonym = SynonymTokenFilter::build(config);
+fn create_synonym(
+    name: &str,
+    config: default!(Option<&str>, "NULL"),
+    source: default!(Option<&str>, "NULL"),
+) {
+    let (config, table_source) = read_config_or_source(config, source);
+    let synonym = SynonymTokenFilter::build(&config);
+    // the content is read from the source table every time, so it's not stored
+    let stored_config = if source.is_some() { "" } else { &config };
 
     pgrx::Spi::connect_mut(|client| {
         let tuptable = client
             .update(
                 r#"
-                INSERT INTO tokenizer_catalog.synonym (name, config) VALUES ($1, $2)
+                INSERT INTO tokenizer_catalog.synonym (name, config, source) VALUES ($1, $2, $3)
                 ON CONFLICT (name) DO NOTHING RETURNING 1
                 "#,
                 Some(1),
-                &[name.into(), config.into()],
+                &[name.into(), stored_config.into(), source.into()],
             )
             .unwrap();
 
@@ -113,6 +335,14 @@ fn create_synonym(name: &str, config: &str) {
             panic!("Synonym already exists: {}", name);
         }
 
+        if let Some(table_source) = &table_source {
+            table_source.create_trigger(
+                &synonym_trigger_name(name),
+                "synonym_source_trigger",
+                name,
+            );
+        }
+
         if SYNONYM_OBJECT_POOL
             .insert(name.to_string(), Arc::new(synonym))
             .is_some()
@@ -120,10 +350,62 @@ fn create_synonym(name: &str, config: &str) {
             panic!("Synonym already exists: {}", name);
         }
     });
+
+    invalidate_catalog(Catalog::Synonym);
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn alter_synonym(
+    name: &str,
+    config: default!(Option<&str>, "NULL"),
+    source: default!(Option<&str>, "NULL"),
+) {
+    let (config, table_source) = read_config_or_source(config, source);
+    let synonym = SynonymTokenFilter::build(&config);
+    let stored_config = if source.is_some() { "" } else { &config };
+    let old_source = get_synonym_source(name);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                r#"
+                UPDATE tokenizer_catalog.synonym SET config = $2, source = $3 WHERE name = $1
+                RETURNING 1
+                "#,
+                Some(1),
+                &[name.into(), stored_config.into(), source.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            panic!("Synonym not found: {}", name);
+        }
+    });
+
+    if let Some(table_source) = old_source.and_then(|s| TableSource::try_parse(&s)) {
+        table_source.drop_trigger(&synonym_trigger_name(name));
+    }
+    if let Some(table_source) = &table_source {
+        table_source.create_trigger(&synonym_trigger_name(name), "synonym_source_trigger", name);
+    }
+
+    SYNONYM_OBJECT_POOL.insert(name.to_string(), Arc::new(synonym));
+    // text analyzers hold the synonym, so they are rebuilt too
+    invalidate_text_analyzers();
+    warn_stale_custom_models(Catalog::Synonym, name);
+
+    invalidate_catalog(Catalog::Synonym);
 }
 
 #[pgrx::pg_extern(volatile, parallel_safe)]
-fn drop_synonym(name: &str) {
+fn drop_synonym(name: &str, cascade: default!(bool, false)) {
+    drop_dependents(Catalog::Synonym, name, cascade);
+
+    // the trigger is dropped with the table if the table does not exist
+    if let Some(table_source) = get_synonym_source(name).and_then(|s| TableSource::try_parse(&s)) {
+        table_source.drop_trigger(&synonym_trigger_name(name));
+    }
+
     pgrx::Spi::connect_mut(|client| {
         let tuptable = client
             .update(
@@ -139,4 +421,22 @@ fn drop_synonym(name: &str) {
     });
 
     SYNONYM_OBJECT_POOL.remove(name);
+    invalidate_catalog(Catalog::Synonym);
+}
+
+#[pgrx::pg_trigger]
+fn synonym_source_trigger<'a>(
+    trigger: &'a pgrx::PgTrigger<'a>,
+) -> Result<Option<PgHeapTuple<'a, AllocatedByPostgres>>, ()> {
+    let tg_argv = trigger.extra_args().expect("trigger arguments are missing");
+    let [name] = tg_argv.as_slice() else {
+        panic!("Invalid trigger arguments");
+    };
+
+    // text analyzers hold the synonyms, so they are rebuilt too
+    SYNONYM_OBJECT_POOL.remove(name);
+    invalidate_text_analyzers();
+
+    invalidate_catalog(Catalog::Synonym);
+    Ok(None)
 }
@@ -0,0 +1,199 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use super::TokenFilter;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct WordDelimiterConfig {
+    pub generate_word_parts: bool,
+    pub generate_number_parts: bool,
+    pub catenate_words: bool,
+    pub catenate_numbers: bool,
+    pub catenate_all: bool,
+    pub preserve_original: bool,
+    pub split_on_case_change: bool,
+    pub split_on_numerics: bool,
+    pub stem_english_possessive: bool,
+}
+
+impl Default for WordDelimiterConfig {
+    fn default() -> Self {
+        Self {
+            generate_word_parts: true,
+            generate_number_parts: true,
+            catenate_words: false,
+            catenate_numbers: false,
+            catenate_all: false,
+            preserve_original: false,
+            split_on_case_change: true,
+            split_on_numerics: true,
+            stem_english_possessive: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharKind {
+    Lower,
+    Upper,
+    Digit,
+    Delimiter,
+}
+
+impl CharKind {
+    fn of(c: char) -> Self {
+        if c.is_uppercase() {
+            CharKind::Upper
+        } else if c.is_alphabetic() {
+            CharKind::Lower
+        } else if c.is_numeric() {
+            CharKind::Digit
+        } else {
+            CharKind::Delimiter
+        }
+    }
+
+    fn is_alpha(self) -> bool {
+        matches!(self, CharKind::Lower | CharKind::Upper)
+    }
+}
+
+struct Part {
+    range: Range<usize>,
+    is_number: bool,
+}
+
+pub struct WordDelimiter {
+    config: WordDelimiterConfig,
+}
+
+impl WordDelimiter {
+    pub fn new(config: WordDelimiterConfig) -> Self {
+        WordDelimiter { config }
+    }
+
+    fn is_break(&self, last: CharKind, current: CharKind) -> bool {
+        if last == current {
+            return false;
+        }
+        if last.is_alpha() && current.is_alpha() {
+            // split "PowerShot" into "Power" and "Shot", but keep "ABCdef" as is
+            return self.config.split_on_case_change
+                && last == CharKind::Lower
+                && current == CharKind::Upper;
+        }
+        self.config.split_on_numerics
+    }
+
+    fn split(&self, token: &str) -> Vec<Part> {
+        let mut parts = Vec::new();
+        let mut current: Option<(usize, CharKind)> = None;
+        let mut last_kind = CharKind::Delimiter;
+
+        for (i, c) in token.char_indices() {
+            let kind = CharKind::of(c);
+            match current {
+                Some((start, start_kind)) if kind == CharKind::Delimiter => {
+                    parts.push(Part {
+                        range: start..i,
+                        is_number: start_kind == CharKind::Digit,
+                    });
+                    current = None;
+                }
+                Some((start, start_kind)) if self.is_break(last_kind, kind) => {
+                    parts.push(Part {
+                        range: start..i,
+                        is_number: start_kind == CharKind::Digit,
+                    });
+                    current = Some((i, kind));
+                }
+                None if kind != CharKind::Delimiter => current = Some((i, kind)),
+                _ => {}
+            }
+            last_kind = kind;
+        }
+        if let Some((start, start_kind)) = current {
+            parts.push(Part {
+                range: start..token.len(),
+                is_number: start_kind == CharKind::Digit,
+            });
+        }
+
+        // "O'Neil's" -> "O", "Neil"
+        if self.config.stem_english_possessive && parts.len() > 1 {
+            let last = parts.last().unwrap();
+            if matches!(&token[last.range.clone()], "s" | "S")
+                && token[..last.range.start].ends_with(['\'', '\u{2019}'])
+            {
+                parts.pop();
+            }
+        }
+
+        parts
+    }
+
+    fn catenate(token: &str, parts: &[Part]) -> String {
+        parts
+            .iter()
+            .map(|part| &token[part.range.clone()])
+            .collect()
+    }
+}
+
+impl TokenFilter for WordDelimiter {
+    fn apply(&self, token: String) -> Vec<String> {
+        let parts = self.split(&token);
+        if parts.is_empty() {
+            return if self.config.preserve_original {
+                vec![token]
+            } else {
+                vec![]
+            };
+        }
+        if parts.len() == 1 && parts[0].range.len() == token.len() {
+            return vec![token];
+        }
+
+        let mut results = Vec::new();
+        if self.config.preserve_original {
+            results.push(token.clone());
+        }
+
+        for part in &parts {
+            let generate = if part.is_number {
+                self.config.generate_number_parts
+            } else {
+                self.config.generate_word_parts
+            };
+            if generate {
+                results.push(token[part.range.clone()].to_string());
+            }
+        }
+
+        let groups = parts
+            .chunk_by(|a, b| a.is_number == b.is_number)
+            .collect::<Vec<_>>();
+        let mut catenated = false;
+        for group in &groups {
+            let catenate = if group[0].is_number {
+                self.config.catenate_numbers
+            } else {
+                self.config.catenate_words
+            };
+            if catenate && group.len() > 1 {
+                results.push(Self::catenate(&token, group));
+                catenated = true;
+            }
+        }
+
+        // skip it if the only group has been catenated already
+        if self.config.catenate_all && parts.len() > 1 && !(groups.len() == 1 && catenated) {
+            results.push(Self::catenate(&token, &parts));
+        }
+
+        results
+    }
+}
@@ -1,14 +1,24 @@
-use std::sync::{Arc, LazyLock};
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    sync::{Arc, LazyLock},
+};
 
-use dashmap::{DashMap, Entry};
+use dashmap::DashMap;
+use pgrx::{iter::TableIterator, name, JsonB};
 use serde::{Deserialize, Serialize};
 use validator::{Validate, ValidationError};
 
 use crate::{
+    cache::{invalidate_catalog, watch_catalog, Catalog},
     character_filter::CharacterFilterConfig,
-    model::{get_model, TokenizerModelPtr},
+    dependency::{drop_dependents, record_dependencies, remove_dependencies},
+    model::{get_model, get_model_kind, is_builtin_model, TokenizerModelPtr},
     pre_tokenizer::PreTokenizerConfig,
-    text_analyzer::{get_text_analyzer, TextAnalyzer, TextAnalyzerConfig, TextAnalyzerPtr},
+    text_analyzer::{
+        get_text_analyzer, get_text_analyzer_config, TextAnalyzer, TextAnalyzerConfig,
+        TextAnalyzerPtr,
+    },
     token_filter::TokenFilterConfig,
     utils::spi_get_one,
 };
@@ -25,6 +35,12 @@ struct TokenizerConfig {
     pre_tokenizer: Option<PreTokenizerConfig>,
     #[serde(default)]
     token_filters: Vec<TokenFilterConfig>,
+    // the text analyzer for queries, the same one as the documents by default
+    #[serde(default)]
+    query_text_analyzer: Option<String>,
+    // token filters appended to the text analyzer for queries only
+    #[serde(default)]
+    query_token_filters: Vec<TokenFilterConfig>,
     model: String,
 }
 
@@ -40,13 +56,42 @@ impl TokenizerConfig {
                 "cannot define both text_analyzer and inlined text_analyzer options",
             ));
         }
+        if self.query_text_analyzer.is_some() && !self.query_token_filters.is_empty() {
+            return Err(ValidationError::new(
+                "cannot define both query_text_analyzer and query_token_filters",
+            ));
+        }
 
         Ok(())
     }
+
+    fn dependencies(&self) -> Vec<(Catalog, String)> {
+        let mut dependencies = match &self.text_analyzer {
+            Some(name) => vec![(Catalog::TextAnalyzer, name.clone())],
+            None => self
+                .token_filters
+                .iter()
+                .filter_map(TokenFilterConfig::dependency)
+                .collect(),
+        };
+        if let Some(name) = &self.query_text_analyzer {
+            dependencies.push((Catalog::TextAnalyzer, name.clone()));
+        }
+        dependencies.extend(
+            self.query_token_filters
+                .iter()
+                .filter_map(TokenFilterConfig::dependency),
+        );
+        if !is_builtin_model(&self.model) {
+            dependencies.push((Catalog::Model, self.model.clone()));
+        }
+        dependencies
+    }
 }
 
 pub struct Tokenizer {
     pub text_analyzer: TextAnalyzerPtr,
+    pub query_text_analyzer: Option<TextAnalyzerPtr>,
     pub model: TokenizerModelPtr,
 }
 pub type TokenizerPtr = Arc<Tokenizer>;
@@ -62,10 +107,23 @@ impl Tokenizer {
             })),
         };
 
+        let query_text_analyzer = match config.query_text_analyzer {
+            Some(name) => Some(get_text_analyzer(&name)),
+            None if !config.query_token_filters.is_empty() => {
+                let mut query_config = text_analyzer.config.clone();
+                query_config
+                    .token_filters
+                    .extend(config.query_token_filters);
+                Some(Arc::new(TextAnalyzer::build(query_config)))
+            }
+            None => None,
+        };
+
         let model = get_model(&config.model);
 
         Tokenizer {
             text_analyzer,
+            query_text_analyzer,
             model,
         }
     }
@@ -74,6 +132,59 @@ impl Tokenizer {
         let tokens = self.text_analyzer.apply(text);
         self.model.apply_batch(tokens)
     }
+
+    // Token ids with the byte ranges in the text they come from. The ranges of the model are
+    // used if the text analyzer keeps the text of the token, otherwise all ids of a token have the
+    // range of the token.
+    pub fn tokenize_with_offsets(&self, text: &str) -> Vec<(u32, Range<usize>)> {
+        let (tokens, spans): (Vec<_>, Vec<_>) = self
+            .text_analyzer
+            .apply_with_spans(text)
+            .into_iter()
+            .unzip();
+        let unchanged = tokens
+            .iter()
+            .zip(&spans)
+            .map(|(token, span)| text.get(span.start..span.end) == Some(token.as_str()))
+            .collect::<Vec<_>>();
+
+        let batches = self.model.apply_batch_with_offsets(tokens);
+        batches
+            .into_iter()
+            .zip(spans.into_iter().zip(unchanged))
+            .flat_map(|(ids, (span, unchanged))| {
+                ids.into_iter().map(move |(id, range)| {
+                    if unchanged {
+                        (id, span.start + range.start..span.start + range.end)
+                    } else {
+                        (id, span.start..span.end)
+                    }
+                })
+            })
+            .collect()
+    }
+
+    pub fn tokenize_batch(&self, texts: &[&str]) -> Vec<Vec<u32>> {
+        let batches = texts
+            .iter()
+            .map(|text| self.text_analyzer.apply(text))
+            .collect();
+        self.model.apply_batches(batches)
+    }
+
+    pub fn count_tokens(&self, text: &str) -> usize {
+        let tokens = self.text_analyzer.apply(text);
+        self.model.count_batch(tokens)
+    }
+
+    pub fn tokenize_query(&self, text: &str) -> Vec<u32> {
+        let text_analyzer = self
+            .query_text_analyzer
+            .as_ref()
+            .unwrap_or(&self.text_analyzer);
+        let tokens = text_analyzer.apply(text);
+        self.model.apply_batch(tokens)
+    }
 }
 
 type TokenizerObjectPool = DashMap<String, TokenizerPtr>;
@@ -95,26 +206,31 @@ pub fn get_tokenizer(name: &str) -> TokenizerPtr {
         return model.clone();
     }
 
-    match TOKENIZER_OBJECT_POOL.entry(name.to_string()) {
-        Entry::Occupied(entry) => entry.get().clone(),
-        Entry::Vacant(entry) => {
-            if let Some(object) = get_tokenizer_from_database(name) {
-                entry.insert(object.clone());
-                return object;
-            }
+    // the entry lock is not held while loading, since the pool may be cleared by the relcache
+    // callback during the query
+    watch_catalog(Catalog::Tokenizer);
+    let Some(object) = get_tokenizer_from_database(name) else {
+        panic!("Tokenizer not found: {}", name);
+    };
+    TOKENIZER_OBJECT_POOL.insert(name.to_string(), object.clone());
+    object
+}
 
-            panic!("Tokenizer not found: {}", name);
-        }
-    }
+pub fn invalidate_tokenizers() {
+    TOKENIZER_OBJECT_POOL.clear();
 }
 
-fn get_tokenizer_from_database(name: &str) -> Option<TokenizerPtr> {
+fn get_tokenizer_config(name: &str) -> Option<TokenizerConfig> {
     let config_bytes: &str = spi_get_one(
         "SELECT config FROM tokenizer_catalog.tokenizer WHERE name = $1",
         &[name.into()],
     )?;
 
-    let config: TokenizerConfig = serde_json::from_str(config_bytes).unwrap();
+    Some(serde_json::from_str(config_bytes).unwrap())
+}
+
+fn get_tokenizer_from_database(name: &str) -> Option<TokenizerPtr> {
+    let config = get_tokenizer_config(name)?;
     Some(Arc::new(Tokenizer::build(config)))
 }
 
@@ -124,6 +240,7 @@ fn create_tokenizer(name: &str, config: &str) {
     config.validate().unwrap();
 
     let config_str = serde_json::to_string(&config).unwrap();
+    let dependencies = config.dependencies();
     let tokenizer = Tokenizer::build(config);
 
     pgrx::Spi::connect_mut(|client| {
@@ -144,10 +261,43 @@ fn create_tokenizer(name: &str, config: &str) {
 
         TOKENIZER_OBJECT_POOL.insert(name.to_string(), Arc::new(tokenizer));
     });
+
+    record_dependencies(Catalog::Tokenizer, name, &dependencies);
+    invalidate_catalog(Catalog::Tokenizer);
 }
 
 #[pgrx::pg_extern(volatile, parallel_safe)]
-fn drop_tokenizer(name: &str) {
+fn alter_tokenizer(name: &str, config: &str) {
+    let config: TokenizerConfig = toml::from_str(config).unwrap();
+    config.validate().unwrap();
+
+    let config_str = serde_json::to_string(&config).unwrap();
+    let dependencies = config.dependencies();
+    let tokenizer = Tokenizer::build(config);
+
+    pgrx::Spi::connect_mut(|client| {
+        let tuptable = client
+            .update(
+                "UPDATE tokenizer_catalog.tokenizer SET config = $2 WHERE name = $1 RETURNING 1",
+                Some(1),
+                &[name.into(), config_str.into()],
+            )
+            .unwrap();
+
+        if tuptable.is_empty() {
+            panic!("Tokenizer not found: {}", name);
+        }
+    });
+
+    TOKENIZER_OBJECT_POOL.insert(name.to_string(), Arc::new(tokenizer));
+    record_dependencies(Catalog::Tokenizer, name, &dependencies);
+    invalidate_catalog(Catalog::Tokenizer);
+}
+
+#[pgrx::pg_extern(volatile, parallel_safe)]
+fn drop_tokenizer(name: &str, cascade: default!(bool, false)) {
+    drop_dependents(Catalog::Tokenizer, name, cascade);
+
     pgrx::Spi::connect_mut(|client| {
         let tuptable = client
             .update(
@@ -163,6 +313,73 @@ fn drop_tokenizer(name: &str) {
     });
 
     TOKENIZER_OBJECT_POOL.remove(name);
+    remove_dependencies(Catalog::Tokenizer, name);
+    invalidate_catalog(Catalog::Tokenizer);
+}
+
+fn model_stage_config(name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "kind": get_model_kind(name),
+        "vocab_size": get_model(name).vocab_size(),
+    })
+}
+
+// Each row is a stage of the tokenizer in the order of application. The stages of a referenced
+// text analyzer are resolved, with `text_analyzer` set to its name. Stages only for queries are
+// prefixed with `query_`.
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn describe_tokenizer(
+    name: &str,
+) -> TableIterator<
+    'static,
+    (
+        name!(position, i32),
+        name!(stage, String),
+        name!(text_analyzer, Option<String>),
+        name!(config, JsonB),
+    ),
+> {
+    let Some(config) = get_tokenizer_config(name) else {
+        panic!("Tokenizer not found: {}", name);
+    };
+    let text_analyzer_config = match &config.text_analyzer {
+        Some(text_analyzer) => get_text_analyzer_config(text_analyzer)
+            .unwrap_or_else(|| panic!("TextAnalyzer not found: {}", text_analyzer)),
+        None => TextAnalyzerConfig {
+            character_filters: config.character_filters,
+            pre_tokenizer: config.pre_tokenizer,
+            token_filters: config.token_filters,
+        },
+    };
+
+    let mut rows = text_analyzer_config
+        .stages()
+        .into_iter()
+        .map(|(stage, value)| (stage.to_string(), config.text_analyzer.clone(), value))
+        .collect::<Vec<_>>();
+    if let Some(query_text_analyzer) = &config.query_text_analyzer {
+        let query_config = get_text_analyzer_config(query_text_analyzer)
+            .unwrap_or_else(|| panic!("TextAnalyzer not found: {}", query_text_analyzer));
+        rows.extend(query_config.stages().into_iter().map(|(stage, value)| {
+            let stage = format!("query_{}", stage);
+            (stage, Some(query_text_analyzer.clone()), value)
+        }));
+    }
+    for filter in &config.query_token_filters {
+        let value = serde_json::to_value(filter).unwrap();
+        rows.push(("query_token_filter".to_string(), None, value));
+    }
+    let model = model_stage_config(&config.model);
+    rows.push(("model".to_string(), None, model));
+
+    TableIterator::new(
+        rows.into_iter()
+            .enumerate()
+            .map(|(i, (stage, text_analyzer, value))| {
+                (i as i32 + 1, stage, text_analyzer, JsonB(value))
+            }),
+    )
 }
 
 #[pgrx::pg_extern(stable, parallel_safe)]
@@ -174,3 +391,213 @@ pub fn tokenize(text: &str, tokenizer_name: &str) -> Vec<i32> {
         .map(|x| x.try_into().unwrap())
         .collect()
 }
+
+// Like `explain_text_analyzer`, with the token ids of the model as the last stage.
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn explain_tokenize(
+    text: &str,
+    tokenizer_name: &str,
+) -> TableIterator<
+    'static,
+    (
+        name!(stage, i32),
+        name!(kind, String),
+        name!(config, JsonB),
+        name!(output, Vec<String>),
+    ),
+> {
+    let tokenizer = get_tokenizer(tokenizer_name);
+    let Some(config) = get_tokenizer_config(tokenizer_name) else {
+        panic!("Tokenizer not found: {}", tokenizer_name);
+    };
+
+    let mut stages = tokenizer.text_analyzer.explain(text);
+    let tokens = match stages.last() {
+        Some((_, _, output)) => output.clone(),
+        None => vec![text.to_string()],
+    };
+    let ids = tokenizer.model.apply_batch(tokens);
+    stages.push((
+        "model",
+        model_stage_config(&config.model),
+        ids.into_iter().map(|id| id.to_string()).collect(),
+    ));
+
+    TableIterator::new(
+        stages
+            .into_iter()
+            .enumerate()
+            .map(|(i, (kind, config, output))| {
+                (i as i32 + 1, kind.to_string(), JsonB(config), output)
+            }),
+    )
+}
+
+// Like `tokenize`, with the text analyzer for queries.
+#[pgrx::pg_extern(stable, parallel_safe)]
+pub fn tokenize_query(text: &str, tokenizer_name: &str) -> Vec<i32> {
+    let tokenizer = get_tokenizer(tokenizer_name);
+    tokenizer
+        .tokenize_query(text)
+        .into_iter()
+        .map(|x| x.try_into().unwrap())
+        .collect()
+}
+
+// the number of occurrences of each token id, ordered by id
+fn term_frequencies(text: &str, tokenizer_name: &str) -> BTreeMap<u32, i32> {
+    let tokenizer = get_tokenizer(tokenizer_name);
+    let mut frequencies = BTreeMap::new();
+    for id in tokenizer.tokenize(text) {
+        *frequencies.entry(id).or_insert(0) += 1;
+    }
+    frequencies
+}
+
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn tokenize_tf(
+    text: &str,
+    tokenizer_name: &str,
+) -> TableIterator<'static, (name!(id, i32), name!(count, i32))> {
+    let frequencies = term_frequencies(text, tokenizer_name);
+    TableIterator::new(
+        frequencies
+            .into_iter()
+            .map(|(id, count)| (id.try_into().unwrap(), count)),
+    )
+}
+
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn tokenize_jsonb(text: &str, tokenizer_name: &str) -> JsonB {
+    let frequencies = term_frequencies(text, tokenizer_name);
+    let object = frequencies
+        .into_iter()
+        .map(|(id, count)| (id.to_string(), serde_json::Value::from(count)))
+        .collect::<serde_json::Map<_, _>>();
+    JsonB(serde_json::Value::Object(object))
+}
+
+// The text representation of a pgvector `sparsevec`, like `{1:2,5:1}/30522`. Indices of
+// `sparsevec` start from 1, so the index of a token is its id plus 1.
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn tokenize_sparsevec(text: &str, tokenizer_name: &str, dims: i32) -> String {
+    if dims <= 0 {
+        panic!("Dimensions must be positive: {}", dims);
+    }
+    let frequencies = term_frequencies(text, tokenizer_name);
+    let elements = frequencies
+        .into_iter()
+        .map(|(id, count)| {
+            if i64::from(id) >= i64::from(dims) {
+                panic!("Token id {} is out of the dimensions {}", id, dims);
+            }
+            format!("{}:{}", id + 1, count)
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}/{}", elements.join(","), dims)
+}
+
+// Tokenize the texts with one lookup of the tokenizer, and of the vocabulary for custom models.
+// Rows are numbered from 1 like `WITH ORDINALITY`, and null texts are skipped.
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn tokenize_batch(
+    texts: Vec<Option<&str>>,
+    tokenizer_name: &str,
+) -> TableIterator<'static, (name!(ordinality, i32), name!(ids, Vec<i32>))> {
+    let tokenizer = get_tokenizer(tokenizer_name);
+    let (ordinalities, texts): (Vec<_>, Vec<_>) = texts
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, text)| text.map(|text| (i as i32 + 1, text)))
+        .unzip();
+    let batches = tokenizer.tokenize_batch(&texts);
+
+    TableIterator::new(ordinalities.into_iter().zip(batches).map(|(i, ids)| {
+        let ids = ids.into_iter().map(|x| x.try_into().unwrap()).collect();
+        (i, ids)
+    }))
+}
+
+// The text analyzer is not reversible, so the tokens of the model are returned, e.g. stemmed
+// words for custom models.
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn detokenize(ids: Vec<i32>, tokenizer_name: &str) -> String {
+    let Some(config) = get_tokenizer_config(tokenizer_name) else {
+        panic!("Tokenizer not found: {}", tokenizer_name);
+    };
+    let tokenizer = get_tokenizer(tokenizer_name);
+    if !tokenizer.model.decodable() {
+        panic!("Model {} does not support decoding", config.model);
+    }
+
+    let ids = ids
+        .into_iter()
+        .map(|id| u32::try_from(id).unwrap_or_else(|_| panic!("Invalid token id: {}", id)))
+        .collect::<Vec<_>>();
+    tokenizer.model.decode(&ids)
+}
+
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn count_tokens(text: &str, tokenizer_name: &str) -> i32 {
+    let tokenizer = get_tokenizer(tokenizer_name);
+    tokenizer.count_tokens(text).try_into().unwrap()
+}
+
+// The longest prefix (`from => 'start'`) or suffix (`from => 'end'`) of the text whose tokens fit
+// in `max_tokens`. The text is cut at the boundaries of the tokens, and since a word cut inside
+// may be tokenized into more tokens, the cut text is tokenized again and the cut moves back a
+// token until it fits.
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn truncate_to_tokens(
+    text: &str,
+    tokenizer_name: &str,
+    max_tokens: i32,
+    from: default!(&str, "'start'"),
+) -> String {
+    let Ok(max_tokens) = usize::try_from(max_tokens) else {
+        panic!("max_tokens must not be negative: {}", max_tokens);
+    };
+    let tokenizer = get_tokenizer(tokenizer_name);
+    let tokens = tokenizer.tokenize_with_offsets(text);
+    if tokens.len() <= max_tokens {
+        return text.to_string();
+    }
+
+    // the cuts keeping `kept` tokens, from `max_tokens` down to none, which always fits
+    let mut cuts = match from {
+        // before the first token that is not kept
+        "start" => (0..=max_tokens)
+            .rev()
+            .map(|kept| {
+                tokens[kept..]
+                    .iter()
+                    .map(|(_, range)| range.start)
+                    .min()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>(),
+        // after the last token that is not kept
+        "end" => (0..=max_tokens)
+            .rev()
+            .map(|kept| {
+                tokens[..tokens.len() - kept]
+                    .iter()
+                    .map(|(_, range)| range.end)
+                    .max()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>(),
+        _ => panic!("Unknown from: {}, expected one of start, end", from),
+    };
+    cuts.dedup();
+
+    cuts.into_iter()
+        .filter(|cut| text.is_char_boundary(*cut))
+        .map(|cut| match from {
+            "start" => &text[..cut],
+            _ => &text[cut..],
+        })
+        .find(|part| tokenizer.count_tokens(part) <= max_tokens)
+        .unwrap_or_default()
+        .to_string()
+}
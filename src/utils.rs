@@ -1,4 +1,10 @@
-use pgrx::{datum::DatumWithOid, pg_sys::panic::ErrorReportable, FromDatum, IntoDatum};
+use std::ffi::CStr;
+
+use pgrx::{
+    datum::DatumWithOid,
+    pg_sys::{panic::ErrorReportable, AsPgCStr},
+    FromDatum, IntoDatum,
+};
 
 pub fn spi_get_one<T>(query: &str, args: &[DatumWithOid]) -> Option<T>
 where
@@ -18,3 +24,106 @@ where
         }
     })
 }
+
+pub fn quote_identifier(ident: &str) -> String {
+    unsafe {
+        let ptr = pgrx::pg_sys::quote_identifier(ident.as_pg_cstr());
+        let quoted_str = CStr::from_ptr(ptr).to_str().unwrap().to_string();
+        pgrx::pg_sys::pfree(ptr as _);
+        quoted_str
+    }
+}
+
+pub fn quote_literal(literal: &str) -> String {
+    unsafe {
+        let ptr = pgrx::pg_sys::quote_literal_cstr(literal.as_pg_cstr());
+        let quoted_str = CStr::from_ptr(ptr).to_str().unwrap().to_string();
+        pgrx::pg_sys::pfree(ptr as _);
+        quoted_str
+    }
+}
+
+// either `config` or `source` should be provided, returns the content and the parsed source
+pub fn read_config_or_source(
+    config: Option<&str>,
+    source: Option<&str>,
+) -> (String, Option<TableSource>) {
+    match (config, source) {
+        (Some(config), None) => (config.to_string(), None),
+        (None, Some(source)) => {
+            let table_source = TableSource::parse(source);
+            (table_source.read(), Some(table_source))
+        }
+        _ => panic!("Exactly one of config and source should be provided"),
+    }
+}
+
+// A source is a reference to a text column of a user table, like `public.my_stopwords(word)`.
+pub struct TableSource {
+    // quoted and schema-qualified if needed, can be used in queries directly
+    pub table: String,
+    pub column: String,
+}
+
+impl TableSource {
+    pub fn parse(source: &str) -> Self {
+        Self::try_parse(source).unwrap_or_else(|| panic!("Table not found: {}", source))
+    }
+
+    // returns `None` if the table does not exist
+    pub fn try_parse(source: &str) -> Option<Self> {
+        let source = source.trim();
+        let Some((table, column)) = source.strip_suffix(')').and_then(|s| s.split_once('(')) else {
+            panic!(
+                "Invalid source: {}, it should be like \"schema.table(column)\"",
+                source
+            );
+        };
+
+        let table: String = spi_get_one(
+            "SELECT to_regclass($1)::text WHERE to_regclass($1) IS NOT NULL",
+            &[table.trim().into()],
+        )?;
+
+        Some(TableSource {
+            table,
+            column: quote_identifier(column.trim()),
+        })
+    }
+
+    // all values of the column, one per line
+    pub fn read(&self) -> String {
+        let query = format!(
+            "SELECT coalesce(string_agg({}::text, E'\\n'), '') FROM {}",
+            self.column, self.table
+        );
+        spi_get_one::<String>(&query, &[]).unwrap()
+    }
+
+    // keep the cached object up to date when the table changes
+    pub fn create_trigger(&self, trigger_name: &str, function: &str, name: &str) {
+        let query = format!(
+            r#"
+            CREATE TRIGGER {}
+            AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE
+            ON {}
+            FOR EACH STATEMENT
+            EXECUTE FUNCTION tokenizer_catalog.{}({})
+            "#,
+            quote_identifier(trigger_name),
+            self.table,
+            function,
+            quote_literal(name)
+        );
+        pgrx::Spi::run(&query).unwrap();
+    }
+
+    pub fn drop_trigger(&self, trigger_name: &str) {
+        let query = format!(
+            "DROP TRIGGER IF EXISTS {} ON {}",
+            quote_identifier(trigger_name),
+            self.table
+        );
+        pgrx::Spi::run(&query).unwrap();
+    }
+}
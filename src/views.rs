@@ -0,0 +1,122 @@
+// Views over the catalog tables, with configs parsed as jsonb and dependencies resolved. Objects
+// in `depends_on` and `used_by` are like `{"kind": "stopwords", "name": "stop1"}`.
+
+use crate::utils::TableSource;
+
+// the content of a dictionary loaded from a table, `NULL` if the table does not exist
+#[pgrx::pg_extern(stable, parallel_safe)]
+fn _pg_tokenizer_source_content(source: &str) -> Option<String> {
+    TableSource::try_parse(source).map(|source| source.read())
+}
+
+pgrx::extension_sql!(
+    r#"
+CREATE FUNCTION tokenizer_catalog.object_dependencies(kind TEXT, name TEXT) RETURNS JSONB
+STABLE LANGUAGE sql AS $$
+    SELECT COALESCE(
+        jsonb_agg(jsonb_build_object('kind', d.ref_kind, 'name', d.ref_name) ORDER BY d.ref_kind, d.ref_name),
+        '[]'::jsonb
+    )
+    FROM tokenizer_catalog.dependency d
+    WHERE d.kind = $1 AND d.name = $2
+$$;
+
+CREATE FUNCTION tokenizer_catalog.object_dependents(kind TEXT, name TEXT) RETURNS JSONB
+STABLE LANGUAGE sql AS $$
+    SELECT COALESCE(
+        jsonb_agg(jsonb_build_object('kind', d.kind, 'name', d.name) ORDER BY d.kind, d.name),
+        '[]'::jsonb
+    )
+    FROM tokenizer_catalog.dependency d
+    WHERE d.ref_kind = $1 AND d.ref_name = $2
+$$;
+
+-- number of distinct entries in a dictionary, lines are trimmed, empty lines and comments are
+-- skipped like the dictionary parsers do
+CREATE FUNCTION tokenizer_catalog._pg_tokenizer_count_entries(content TEXT) RETURNS INT
+IMMUTABLE LANGUAGE sql AS $$
+    SELECT count(DISTINCT entry)::INT
+    FROM (
+        SELECT regexp_replace(line, '^\s+|\s+$', '', 'g') AS entry
+        FROM regexp_split_to_table($1, '\n') AS line
+    ) lines
+    WHERE entry <> '' AND entry NOT LIKE '#%'
+$$;
+
+CREATE VIEW tokenizer_catalog.text_analyzers AS
+SELECT
+    t.name,
+    t.config::jsonb AS config,
+    tokenizer_catalog.object_dependencies('text_analyzer', t.name) AS depends_on,
+    tokenizer_catalog.object_dependents('text_analyzer', t.name) AS used_by
+FROM tokenizer_catalog.text_analyzer t;
+
+CREATE VIEW tokenizer_catalog.tokenizers AS
+SELECT
+    t.name,
+    t.config::jsonb AS config,
+    t.config::jsonb ->> 'text_analyzer' AS text_analyzer,
+    t.config::jsonb ->> 'query_text_analyzer' AS query_text_analyzer,
+    t.config::jsonb ->> 'model' AS model,
+    tokenizer_catalog.model_kind(t.config::jsonb ->> 'model') AS model_kind,
+    tokenizer_catalog.object_dependencies('tokenizer', t.name) AS depends_on
+FROM tokenizer_catalog.tokenizer t;
+
+CREATE VIEW tokenizer_catalog.models AS
+SELECT
+    m.name,
+    m.kind,
+    m.config,
+    tokenizer_catalog._pg_tokenizer_stored_vocab_size(m.name) AS vocab_size,
+    m.name = ANY(tokenizer_catalog.list_preload_models()) AS preload,
+    tokenizer_catalog.object_dependencies('model', m.name) AS depends_on,
+    tokenizer_catalog.object_dependents('model', m.name) AS used_by
+FROM (
+    SELECT name, 'builtin' AS kind, NULL::jsonb AS config
+    FROM unnest(tokenizer_catalog.list_builtin_models()) AS name
+    UNION ALL
+    -- huggingface configs are stored as a json string
+    SELECT
+        name,
+        tokenizer_catalog.model_kind(name),
+        CASE jsonb_typeof(c.value) WHEN 'string' THEN (c.value #>> '{}')::jsonb ELSE c.value END
+    FROM tokenizer_catalog.model, jsonb_each(config::jsonb) c
+) m;
+
+CREATE VIEW tokenizer_catalog.stopwords_lists AS
+SELECT
+    s.name,
+    tokenizer_catalog._pg_tokenizer_count_entries(
+        COALESCE(tokenizer_catalog._pg_tokenizer_source_content(s.source), s.config)
+    ) AS size,
+    s.source,
+    s.name IN (SELECT b.name FROM tokenizer_catalog.list_builtin_stopwords() b) AS builtin,
+    tokenizer_catalog.object_dependents('stopwords', s.name) AS used_by
+FROM tokenizer_catalog.stopwords s;
+
+CREATE VIEW tokenizer_catalog.synonym_sets AS
+SELECT
+    s.name,
+    tokenizer_catalog._pg_tokenizer_count_entries(
+        COALESCE(tokenizer_catalog._pg_tokenizer_source_content(s.source), s.config)
+    ) AS rules,
+    s.source,
+    tokenizer_catalog.object_dependents('synonym', s.name) AS used_by
+FROM tokenizer_catalog.synonym s;
+"#,
+    name = "catalog_views",
+    requires = [
+        "text_analyzer_table",
+        "tokenizer_table",
+        "model_table",
+        "stopwords_table",
+        "synonym_table",
+        "dependency_table",
+        model_kind,
+        _pg_tokenizer_stored_vocab_size,
+        _pg_tokenizer_source_content,
+        list_builtin_models,
+        list_preload_models,
+        list_builtin_stopwords,
+    ]
+);